@@ -0,0 +1,107 @@
+//! Lexer throughput on large generated programs.
+//!
+//! Run with `cargo bench --bench lexer`. There are no benchmark dependencies,
+//! so this is a plain binary timing a few runs of each case and printing the
+//! best one.
+
+use std::{hint::black_box, time::Instant};
+
+use loxite::lexer::Lexer;
+
+const RUNS: usize = 10;
+
+/// About 10 MB of source covering every kind of token, with comments and
+/// non-ASCII text mixed in
+fn mixed_source() -> String {
+    let mut source = String::new();
+    for i in 0..100_000 {
+        source.push_str(&format!(
+            "(value_{i} + 0x{i:X} * {i}.5e-3) >= -\"naïve ${{count}} {i}\" == !true ? nil : 1_000, // line {i}\n"
+        ));
+        if i % 10 == 0 {
+            source.push_str("/* a block comment\n   over two lines */\n");
+        }
+    }
+    source
+}
+
+/// Keywords and identifiers only, many of them sharing a prefix with a keyword
+fn identifier_source() -> String {
+    let words = [
+        "and",
+        "android",
+        "class",
+        "classic",
+        "else",
+        "elsewhere",
+        "false",
+        "fun",
+        "funnel",
+        "for",
+        "format",
+        "if",
+        "iffy",
+        "nil",
+        "or",
+        "order",
+        "print",
+        "printer",
+        "return",
+        "super",
+        "this",
+        "thistle",
+        "true",
+        "truest",
+        "var",
+        "variable",
+        "while",
+        "whilst",
+        "x",
+        "total_count",
+    ];
+    let mut source = String::new();
+    for i in 0..50_000 {
+        for word in words {
+            source.push_str(word);
+            source.push(' ');
+        }
+        source.push_str(&format!("name{i}\n"));
+    }
+    source
+}
+
+/// Times `lex` over `source` and prints the best run
+fn bench(name: &str, source: &str, lex: impl Fn(&str) -> usize) {
+    let megabytes = source.len() as f64 / 1_000_000.0;
+
+    let mut best = f64::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = lex(black_box(source));
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+
+    println!(
+        "{:<12} {:.1} MB into {} tokens in {:.1} ms ({:.0} MB/s)",
+        name,
+        megabytes,
+        tokens,
+        best * 1000.0,
+        megabytes / best
+    );
+}
+
+fn main() {
+    let whole_file = |source: &str| Lexer::new(source).scan_tokens().len();
+    bench("mixed", &mixed_source(), whole_file);
+    bench("identifiers", &identifier_source(), whole_file);
+
+    // A fresh lexer per line, like the prompt does
+    bench("per line", &identifier_source(), |source| {
+        source
+            .lines()
+            .map(|line| Lexer::new(line).scan_tokens().len())
+            .sum()
+    });
+}
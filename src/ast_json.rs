@@ -0,0 +1,511 @@
+//! Conversion of the AST to and from JSON, so other tools can analyse Lox
+//! programs or hand them back to be executed.
+//!
+//! Every expression is an object with a `kind` and the `span` of source it
+//! covers. Nodes with operands list them in `children`, in source order, and
+//! nodes built around an operator carry it as a token in `operator`:
+//!
+//! ```text
+//! { "kind": "Binary",   "span": <span>, "operator": <token>, "children": [<left>, <right>] }
+//! { "kind": "Unary",    "span": <span>, "operator": <token>, "children": [<operand>] }
+//! { "kind": "Grouping", "span": <span>, "children": [<expression>] }
+//! { "kind": "Conditional", "span": <span>, "children": [<condition>, <then>, <else>] }
+//! { "kind": "Interpolation", "span": <span>, "strings": [<text>, ...], "children": [<expression>, ...] }
+//! { "kind": "Literal",  "span": <span>, "value": <literal>, "lexeme": "0xFF" }
+//! ```
+//!
+//! A span holds byte offsets into the UTF-8 source, `end` being exclusive:
+//! `{ "start": 0, "end": 1 }`. The spans of unary, binary and conditional
+//! nodes follow from their operator and children, so they are ignored when
+//! reading; the others are optional, for ASTs built by other tools.
+//!
+//! A token records its `type` (the `TokenType` variant name), its `lexeme`,
+//! its `literal`, the `line` it starts on and its `span`:
+//!
+//! ```text
+//! { "type": "Minus", "lexeme": "-", "literal": null, "line": 1,
+//!   "span": { "start": 0, "end": 1 } }
+//! ```
+//!
+//! An interpolated string keeps the text around its embedded expressions in
+//! `strings`, which has one more element than `children`.
+//!
+//! A number literal keeps the `lexeme` it was written as, so printers can
+//! write it back unchanged. It is left out for other literals and is optional
+//! when reading, but must then lex as the number in `value`. Binary and unary
+//! operators must be ones the interpreter evaluates.
+//!
+//! Literals map to the JSON value of the same type: a string, a number,
+//! `true`/`false`, or `null` for `nil`. JSON has no numbers for infinities
+//! and NaN, which are written as an object holding their name instead:
+//!
+//! ```text
+//! { "number": "Infinity" }    { "number": "-Infinity" }    { "number": "NaN" }
+//! ```
+
+use std::borrow::Cow;
+
+use crate::{
+    ast_printer::ExprPrinter,
+    expr::{
+        BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+    },
+    json::{FromJson, JsonError, JsonValue, ToJson},
+    lexer::Lexer,
+    parser::infix_operator,
+    token::{Span, Token, TokenLiteral, TokenType},
+};
+
+/// Prints an expression as indented JSON in the schema above
+pub struct JsonPrinter;
+
+impl ExprPrinter for JsonPrinter {
+    fn print(&self, expr: &Expr) -> String {
+        expr.to_json().to_pretty_string()
+    }
+}
+
+impl ToJson for Expr<'_> {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            Expr::Binary(expr) => JsonValue::object(vec![
+                ("kind", JsonValue::String("Binary".to_string())),
+                ("span", self.span().to_json()),
+                ("operator", expr.operator.to_json()),
+                (
+                    "children",
+                    JsonValue::Array(vec![expr.left.to_json(), expr.right.to_json()]),
+                ),
+            ]),
+            Expr::Unary(expr) => JsonValue::object(vec![
+                ("kind", JsonValue::String("Unary".to_string())),
+                ("span", self.span().to_json()),
+                ("operator", expr.operator.to_json()),
+                ("children", JsonValue::Array(vec![expr.right.to_json()])),
+            ]),
+            Expr::Grouping(expr) => JsonValue::object(vec![
+                ("kind", JsonValue::String("Grouping".to_string())),
+                ("span", self.span().to_json()),
+                (
+                    "children",
+                    JsonValue::Array(vec![expr.expression.to_json()]),
+                ),
+            ]),
+            Expr::Conditional(expr) => JsonValue::object(vec![
+                ("kind", JsonValue::String("Conditional".to_string())),
+                ("span", self.span().to_json()),
+                (
+                    "children",
+                    JsonValue::Array(vec![
+                        expr.condition.to_json(),
+                        expr.then_branch.to_json(),
+                        expr.else_branch.to_json(),
+                    ]),
+                ),
+            ]),
+            Expr::Interpolation(expr) => JsonValue::object(vec![
+                ("kind", JsonValue::String("Interpolation".to_string())),
+                ("span", self.span().to_json()),
+                (
+                    "strings",
+                    JsonValue::Array(
+                        expr.strings
+                            .iter()
+                            .map(|string| JsonValue::String(string.clone()))
+                            .collect(),
+                    ),
+                ),
+                (
+                    "children",
+                    JsonValue::Array(expr.expressions.iter().map(Expr::to_json).collect()),
+                ),
+            ]),
+            Expr::Literal(expr) => {
+                let mut members = vec![
+                    ("kind", JsonValue::String("Literal".to_string())),
+                    ("span", self.span().to_json()),
+                    ("value", expr.value.to_json()),
+                ];
+                if let Some(lexeme) = &expr.lexeme {
+                    members.push(("lexeme", JsonValue::String(lexeme.clone())));
+                }
+                JsonValue::object(members)
+            }
+        }
+    }
+}
+
+impl FromJson for Expr<'static> {
+    fn from_json(json: &JsonValue) -> Result<Self, JsonError> {
+        let kind = field(json, "kind")?
+            .as_str()
+            .ok_or_else(|| JsonError::new("'kind' must be a string"))?;
+
+        match kind {
+            "Binary" => {
+                let [left, right] = children::<2>(json)?;
+                let operator = Token::from_json(field(json, "operator")?)?;
+                // `?` is in the infix table but builds a conditional
+                if operator.token_type == TokenType::Question
+                    || infix_operator(operator.token_type).is_none()
+                {
+                    return Err(invalid_operator("binary", &operator));
+                }
+                Ok(Expr::Binary(Box::new(BinaryExpr {
+                    left,
+                    operator,
+                    right,
+                })))
+            }
+            "Unary" => {
+                let [right] = children::<1>(json)?;
+                let operator = Token::from_json(field(json, "operator")?)?;
+                if !matches!(operator.token_type, TokenType::Minus | TokenType::Bang) {
+                    return Err(invalid_operator("unary", &operator));
+                }
+                Ok(Expr::Unary(Box::new(UnaryExpr { operator, right })))
+            }
+            "Grouping" => {
+                let [expression] = children::<1>(json)?;
+                Ok(Expr::Grouping(Box::new(GroupingExpr {
+                    expression,
+                    span: node_span(json)?,
+                })))
+            }
+            "Conditional" => {
+                let [condition, then_branch, else_branch] = children::<3>(json)?;
+                Ok(Expr::Conditional(Box::new(ConditionalExpr {
+                    condition,
+                    then_branch,
+                    else_branch,
+                })))
+            }
+            "Interpolation" => {
+                let strings = field(json, "strings")?
+                    .as_array()
+                    .ok_or_else(|| JsonError::new("'strings' must be an array"))?
+                    .iter()
+                    .map(|string| {
+                        string
+                            .as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| JsonError::new("'strings' must only hold strings"))
+                    })
+                    .collect::<Result<Vec<String>, JsonError>>()?;
+                let expressions = all_children(json)?;
+                if strings.len() != expressions.len() + 1 {
+                    return Err(JsonError::new(&format!(
+                        "Expected {} strings, found {}",
+                        expressions.len() + 1,
+                        strings.len()
+                    )));
+                }
+                Ok(Expr::Interpolation(Box::new(InterpolationExpr {
+                    strings,
+                    expressions,
+                    span: node_span(json)?,
+                })))
+            }
+            "Literal" => {
+                let value = TokenLiteral::from_json(field(json, "value")?)?;
+                let lexeme = match json.get("lexeme") {
+                    Some(lexeme) => Some(number_lexeme(lexeme, &value)?),
+                    None => None,
+                };
+                Ok(Expr::Literal(LiteralExpr {
+                    value,
+                    lexeme,
+                    span: node_span(json)?,
+                }))
+            }
+            kind => Err(JsonError::new(&format!(
+                "Unknown expression kind '{}'",
+                kind
+            ))),
+        }
+    }
+}
+
+impl ToJson for Token<'_> {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object(vec![
+            ("type", JsonValue::String(format!("{:?}", self.token_type))),
+            ("lexeme", JsonValue::String(self.lexeme.to_string())),
+            ("literal", self.literal.to_json()),
+            ("line", JsonValue::Number(self.line as f64)),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Token<'static> {
+    fn from_json(json: &JsonValue) -> Result<Self, JsonError> {
+        let token_type = field(json, "type")?
+            .as_str()
+            .ok_or_else(|| JsonError::new("'type' must be a string"))?
+            .parse::<TokenType>()
+            .map_err(|message| JsonError::new(&message))?;
+        let lexeme = field(json, "lexeme")?
+            .as_str()
+            .ok_or_else(|| JsonError::new("'lexeme' must be a string"))?;
+
+        Ok(Token {
+            token_type,
+            lexeme: Cow::Owned(lexeme.to_string()),
+            literal: TokenLiteral::from_json(field(json, "literal")?)?,
+            line: offset(json, "line")?,
+            span: Span::from_json(field(json, "span")?)?,
+        })
+    }
+}
+
+impl ToJson for Span {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object(vec![
+            ("start", JsonValue::Number(self.start as f64)),
+            ("end", JsonValue::Number(self.end as f64)),
+        ])
+    }
+}
+
+impl FromJson for Span {
+    fn from_json(json: &JsonValue) -> Result<Self, JsonError> {
+        Ok(Span {
+            start: offset(json, "start")?,
+            end: offset(json, "end")?,
+        })
+    }
+}
+
+impl ToJson for TokenLiteral {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            TokenLiteral::String(value) => JsonValue::String(value.clone()),
+            TokenLiteral::Number(value) if value.is_finite() => JsonValue::Number(*value),
+            TokenLiteral::Number(value) => {
+                let name = match *value {
+                    f64::INFINITY => "Infinity",
+                    f64::NEG_INFINITY => "-Infinity",
+                    _ => "NaN",
+                };
+                JsonValue::object(vec![("number", JsonValue::String(name.to_string()))])
+            }
+            TokenLiteral::Boolean(value) => JsonValue::Boolean(*value),
+            TokenLiteral::Empty => JsonValue::Null,
+        }
+    }
+}
+
+impl FromJson for TokenLiteral {
+    fn from_json(json: &JsonValue) -> Result<Self, JsonError> {
+        match json {
+            JsonValue::String(value) => Ok(TokenLiteral::String(value.clone())),
+            JsonValue::Number(value) => Ok(TokenLiteral::Number(*value)),
+            JsonValue::Boolean(value) => Ok(TokenLiteral::Boolean(*value)),
+            JsonValue::Null => Ok(TokenLiteral::Empty),
+            JsonValue::Object(_) => match json.get("number").and_then(JsonValue::as_str) {
+                Some("Infinity") => Ok(TokenLiteral::Number(f64::INFINITY)),
+                Some("-Infinity") => Ok(TokenLiteral::Number(f64::NEG_INFINITY)),
+                Some("NaN") => Ok(TokenLiteral::Number(f64::NAN)),
+                _ => Err(JsonError::new(
+                    "A number object must be Infinity, -Infinity or NaN",
+                )),
+            },
+            _ => Err(JsonError::new(
+                "A literal must be a string, number, boolean or null",
+            )),
+        }
+    }
+}
+
+/// The `span` of a node that keeps its own, which may be left out
+fn node_span(json: &JsonValue) -> Result<Span, JsonError> {
+    json.get("span")
+        .map_or(Ok(Span::default()), Span::from_json)
+}
+
+/// The lexeme of a number literal, which must lex as that same number, so
+/// printers never show a different value from the one that is evaluated
+fn number_lexeme(lexeme: &JsonValue, value: &TokenLiteral) -> Result<String, JsonError> {
+    let lexeme = lexeme
+        .as_str()
+        .ok_or_else(|| JsonError::new("A lexeme must be a string"))?;
+    let mut lexer = Lexer::new(lexeme).quiet();
+    let tokens = lexer.scan_tokens();
+    match (tokens.as_slice(), value) {
+        ([token, _], TokenLiteral::Number(value))
+            if !lexer.had_error
+                && token.token_type == TokenType::Number
+                && token.lexeme == lexeme
+                && token.literal == TokenLiteral::Number(*value) =>
+        {
+            Ok(lexeme.to_string())
+        }
+        _ => Err(JsonError::new(&format!(
+            "The lexeme '{}' does not match the value {}",
+            lexeme,
+            value.to_source()
+        ))),
+    }
+}
+
+fn invalid_operator(kind: &str, operator: &Token) -> JsonError {
+    JsonError::new(&format!(
+        "'{:?}' is not a {} operator",
+        operator.token_type, kind
+    ))
+}
+
+fn field<'a>(json: &'a JsonValue, key: &str) -> Result<&'a JsonValue, JsonError> {
+    json.get(key)
+        .ok_or_else(|| JsonError::new(&format!("Missing field '{}'", key)))
+}
+
+fn offset(json: &JsonValue, key: &str) -> Result<usize, JsonError> {
+    match field(json, key)?.as_f64() {
+        Some(value) if value >= 0.0 && value.fract() == 0.0 => Ok(value as usize),
+        _ => Err(JsonError::new(&format!(
+            "'{}' must be a non-negative integer",
+            key
+        ))),
+    }
+}
+
+fn all_children(json: &JsonValue) -> Result<Vec<Expr<'static>>, JsonError> {
+    field(json, "children")?
+        .as_array()
+        .ok_or_else(|| JsonError::new("'children' must be an array"))?
+        .iter()
+        .map(Expr::from_json)
+        .collect()
+}
+
+fn children<const N: usize>(json: &JsonValue) -> Result<[Expr<'static>; N], JsonError> {
+    all_children(json)?
+        .try_into()
+        .map_err(|children: Vec<Expr>| {
+            JsonError::new(&format!(
+                "Expected {} children, found {}",
+                N,
+                children.len()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        expr::{Expr, LiteralExpr},
+        json::{FromJson, JsonValue, ToJson},
+        lexer::Lexer,
+        parser::Parser,
+        token::TokenLiteral,
+    };
+
+    fn parse(source: &str) -> Expr<'_> {
+        let tokens = Lexer::new(source).scan_tokens();
+        Parser::new(tokens).parser().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_parser_output() {
+        let sources = [
+            "(1 + 2) * -3.5 == !true",
+            "\"a b\" + \"é\" != nil",
+            "1 < 2 == (3 >= 4) / 5",
+            "true ? 1 : nil ? 2 : 3",
+            "\"a ${1 + 2} b ${\"c ${nil}\"}\"",
+            "1e999 + 0x1F",
+        ];
+        for source in sources {
+            let expr = parse(source);
+            let json = expr.to_json().to_pretty_string();
+            let loaded = Expr::from_json(&JsonValue::parse(&json).unwrap()).unwrap();
+            assert_eq!(loaded, expr, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_schema() {
+        let json = parse("-1").to_json().to_string();
+        assert_eq!(
+            json,
+            r#"{"kind":"Unary","span":{"start":0,"end":2},"operator":{"type":"Minus","lexeme":"-","literal":null,"line":1,"span":{"start":0,"end":1}},"children":[{"kind":"Literal","span":{"start":1,"end":2},"value":1,"lexeme":"1"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_non_finite_numbers() {
+        let json = parse("1e999").to_json().to_string();
+        assert_eq!(
+            json,
+            r#"{"kind":"Literal","span":{"start":0,"end":5},"value":{"number":"Infinity"},"lexeme":"1e999"}"#
+        );
+
+        let json = JsonValue::parse(r#"{"kind": "Literal", "value": {"number": "NaN"}}"#);
+        match Expr::from_json(&json.unwrap()).unwrap() {
+            Expr::Literal(LiteralExpr {
+                value: TokenLiteral::Number(value),
+                lexeme: None,
+                ..
+            }) => assert!(value.is_nan()),
+            expr => panic!("Expected NaN, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_invalid_ast() {
+        let sources = [
+            r#"{"kind": "Ternary", "children": []}"#,
+            r#"{"kind": "Conditional", "children": [{"kind": "Literal", "value": 1}]}"#,
+            r#"{"kind": "Grouping", "children": []}"#,
+            r#"{"kind": "Literal", "value": [1]}"#,
+            r#"{"kind": "Literal", "value": {"number": "infinity"}}"#,
+            r#"{"kind": "Literal", "value": 1, "lexeme": 1}"#,
+            r#"{"kind": "Literal", "value": 1, "lexeme": "2"}"#,
+            r#"{"kind": "Literal", "value": 1, "lexeme": " 1"}"#,
+            r#"{"kind": "Literal", "value": -1, "lexeme": "-1"}"#,
+            r#"{"kind": "Literal", "value": "1", "lexeme": "1"}"#,
+            r#"{"kind": "Literal", "value": 1, "span": {"start": -1, "end": 1}}"#,
+            r#"{"kind": "Unary", "children": [{"kind": "Literal", "value": 1}]}"#,
+            r#"{"kind": "Interpolation", "strings": ["a"], "children": [{"kind": "Literal", "value": 1}]}"#,
+            r#"{"kind": "Interpolation", "strings": [1], "children": []}"#,
+        ];
+        for source in sources {
+            let json = JsonValue::parse(source).unwrap();
+            assert!(Expr::from_json(&json).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_invalid_operators() {
+        let one = r#"{"kind": "Literal", "value": 1}"#;
+        let operator = |token_type: &str| {
+            format!(
+                r#"{{"type": "{}", "lexeme": "", "literal": null, "line": 1, "span": {{"start": 0, "end": 0}}}}"#,
+                token_type
+            )
+        };
+        for token_type in ["Dot", "Question", "EOF", "Bang"] {
+            let source = format!(
+                r#"{{"kind": "Binary", "operator": {}, "children": [{}, {}]}}"#,
+                operator(token_type),
+                one,
+                one
+            );
+            let error = Expr::from_json(&JsonValue::parse(&source).unwrap()).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("JSON error: '{}' is not a binary operator", token_type)
+            );
+        }
+        for token_type in ["Plus", "Dot"] {
+            let source = format!(
+                r#"{{"kind": "Unary", "operator": {}, "children": [{}]}}"#,
+                operator(token_type),
+                one
+            );
+            assert!(Expr::from_json(&JsonValue::parse(&source).unwrap()).is_err());
+        }
+    }
+}
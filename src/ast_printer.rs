@@ -1,69 +1,227 @@
-use crate::{expr::Expr, token::TokenLiteral};
+use crate::{
+    expr::{
+        BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+    },
+    token::{TokenLiteral, TokenType},
+    visitor::Visitor,
+};
 
+/// Renders an expression tree as text in some notation
+pub trait ExprPrinter {
+    fn print(&self, expr: &Expr) -> String;
+}
+
+/// Lisp-style S-expressions: `(* (- 123) (group 45.67))`
 pub struct AstPrinter;
 
 impl AstPrinter {
     pub fn get_expr_as_str(&self, expr: &Expr) -> String {
-        expr.print_expr()
+        AstPrinter.visit_expr(expr)
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: Vec<&Expr>) -> String {
+        let mut result = String::new();
+        result.push('(');
+        result.push_str(name);
+
+        exprs.iter().for_each(|expr| {
+            result.push(' ');
+            result.push_str(self.visit_expr(expr).as_str())
+        });
+
+        result.push(')');
+        result
     }
 }
 
-impl Expr {
-    fn print_expr(&self) -> String {
-        match self {
-            Expr::Binary(expr) => {
-                parenthesize(&expr.operator.lexeme, vec![&expr.left, &expr.right])
-            }
-            Expr::Grouping(expr) => parenthesize("group", vec![&expr.expression]),
-            Expr::Unary(expr) => parenthesize(&expr.operator.lexeme, vec![&expr.right]),
-            Expr::Literal(expr) => match &expr.value {
-                TokenLiteral::Empty => "nil".to_string(),
-                _ => expr.value.to_string(),
-            },
+impl ExprPrinter for AstPrinter {
+    fn print(&self, expr: &Expr) -> String {
+        self.get_expr_as_str(expr)
+    }
+}
+
+impl Visitor<String> for AstPrinter {
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> String {
+        self.parenthesize(&expr.operator.lexeme, vec![&expr.right])
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> String {
+        self.parenthesize(&expr.operator.lexeme, vec![&expr.left, &expr.right])
+    }
+
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> String {
+        self.parenthesize("group", vec![&expr.expression])
+    }
+
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> String {
+        self.parenthesize(
+            "?:",
+            vec![&expr.condition, &expr.then_branch, &expr.else_branch],
+        )
+    }
+
+    /// `(interpolate "text" expr "text")`, with the text segments quoted
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> String {
+        let mut result = String::from("(interpolate ");
+        result.push_str(&TokenLiteral::String(expr.strings[0].clone()).to_source());
+        for (expression, string) in expr.expressions.iter().zip(&expr.strings[1..]) {
+            result.push(' ');
+            result.push_str(&self.visit_expr(expression));
+            result.push(' ');
+            result.push_str(&TokenLiteral::String(string.clone()).to_source());
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_literal(&mut self, expr: &LiteralExpr) -> String {
+        expr.to_source()
+    }
+}
+
+/// Reverse Polish Notation: `123 neg 45.67 *`. Groupings disappear, and unary
+/// minus is written `neg` so it cannot be confused with subtraction.
+pub struct RpnPrinter;
+
+impl ExprPrinter for RpnPrinter {
+    fn print(&self, expr: &Expr) -> String {
+        RpnPrinter.visit_expr(expr)
+    }
+}
+
+impl Visitor<String> for RpnPrinter {
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> String {
+        let operator = match expr.operator.token_type {
+            TokenType::Minus => "neg",
+            _ => &expr.operator.lexeme,
+        };
+        format!("{} {}", self.visit_expr(&expr.right), operator)
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> String {
+        format!(
+            "{} {} {}",
+            self.visit_expr(&expr.left),
+            self.visit_expr(&expr.right),
+            expr.operator.lexeme
+        )
+    }
+
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> String {
+        self.visit_expr(&expr.expression)
+    }
+
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> String {
+        format!(
+            "{} {} {} ?:",
+            self.visit_expr(&expr.condition),
+            self.visit_expr(&expr.then_branch),
+            self.visit_expr(&expr.else_branch)
+        )
+    }
+
+    /// The text segments and values in order, then `interpolate` with the
+    /// number of parts it joins: `"a" 1 "" interpolate(3)`
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> String {
+        let mut parts = vec![TokenLiteral::String(expr.strings[0].clone()).to_source()];
+        for (expression, string) in expr.expressions.iter().zip(&expr.strings[1..]) {
+            parts.push(self.visit_expr(expression));
+            parts.push(TokenLiteral::String(string.clone()).to_source());
         }
+        format!("{} interpolate({})", parts.join(" "), parts.len())
+    }
+
+    fn visit_literal(&mut self, expr: &LiteralExpr) -> String {
+        expr.to_source()
+    }
+}
+
+/// Infix with every operation in parentheses: `((-123) * 45.67)`. Source
+/// groupings are dropped, since the parentheses already show how the
+/// expression was parsed.
+pub struct InfixPrinter;
+
+impl ExprPrinter for InfixPrinter {
+    fn print(&self, expr: &Expr) -> String {
+        InfixPrinter.visit_expr(expr)
     }
 }
 
-fn parenthesize(name: &str, exprs: Vec<&Expr>) -> String {
-    let mut result = String::new();
-    result.push_str("(");
-    result.push_str(name);
+impl Visitor<String> for InfixPrinter {
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> String {
+        format!("({}{})", expr.operator.lexeme, self.visit_expr(&expr.right))
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> String {
+        let left = self.visit_expr(&expr.left);
+        let right = self.visit_expr(&expr.right);
+        match expr.operator.token_type {
+            TokenType::Comma => format!("({}, {})", left, right),
+            _ => format!("({} {} {})", left, expr.operator.lexeme, right),
+        }
+    }
 
-    exprs.iter().for_each(|expr| {
-        result.push_str(" ");
-        result.push_str(expr.print_expr().as_str())
-    });
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> String {
+        self.visit_expr(&expr.expression)
+    }
 
-    result.push_str(")");
-    result
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> String {
+        format!(
+            "({} ? {} : {})",
+            self.visit_expr(&expr.condition),
+            self.visit_expr(&expr.then_branch),
+            self.visit_expr(&expr.else_branch)
+        )
+    }
+
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> String {
+        expr.to_source(|expression| self.visit_expr(expression))
+    }
+
+    fn visit_literal(&mut self, expr: &LiteralExpr) -> String {
+        expr.to_source()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        ast_printer::AstPrinter,
+        ast_printer::{AstPrinter, ExprPrinter, InfixPrinter, RpnPrinter},
         expr::{BinaryExpr, Expr, GroupingExpr, LiteralExpr, UnaryExpr},
-        token::{Token, TokenLiteral, TokenType},
+        lexer::Lexer,
+        parser::Parser,
+        token::{Span, Token, TokenLiteral, TokenType},
     };
 
+    fn parse(source: &str) -> Expr<'_> {
+        let tokens = Lexer::new(source).scan_tokens();
+        Parser::new(tokens).parser().unwrap()
+    }
+
     #[test]
     fn test_small_expressions() {
         let expr1 = Expr::Unary(Box::new(UnaryExpr {
             operator: Token {
                 token_type: TokenType::Minus,
-                lexeme: "-".to_string(),
+                lexeme: "-".into(),
                 literal: TokenLiteral::Empty,
                 line: 1,
+                span: Span::default(),
             },
             right: Expr::Literal(LiteralExpr {
-                value: TokenLiteral::Number(123 as f64),
+                value: TokenLiteral::Number(123.0),
+                lexeme: None,
+                span: Span::default(),
             }),
         }));
 
         let expr2 = Expr::Grouping(Box::new(GroupingExpr {
             expression: Expr::Literal(LiteralExpr {
                 value: TokenLiteral::Number(45.67),
+                lexeme: None,
+                span: Span::default(),
             }),
+            span: Span::default(),
         }));
 
         let ast_printer = AstPrinter;
@@ -77,24 +235,31 @@ mod tests {
             left: Expr::Unary(Box::new(UnaryExpr {
                 operator: Token {
                     token_type: TokenType::Minus,
-                    lexeme: "-".to_string(),
+                    lexeme: "-".into(),
                     literal: TokenLiteral::Empty,
                     line: 1,
+                    span: Span::default(),
                 },
                 right: Expr::Literal(LiteralExpr {
-                    value: TokenLiteral::Number(123 as f64),
+                    value: TokenLiteral::Number(123.0),
+                    lexeme: None,
+                    span: Span::default(),
                 }),
             })),
             operator: Token {
                 token_type: TokenType::Star,
-                lexeme: "*".to_string(),
+                lexeme: "*".into(),
                 literal: TokenLiteral::Empty,
                 line: 1,
+                span: Span::default(),
             },
             right: Expr::Grouping(Box::new(GroupingExpr {
                 expression: Expr::Literal(LiteralExpr {
                     value: TokenLiteral::Number(45.67),
+                    lexeme: None,
+                    span: Span::default(),
                 }),
+                span: Span::default(),
             })),
         }));
 
@@ -104,4 +269,85 @@ mod tests {
             "(* (- 123) (group 45.67))"
         );
     }
+
+    #[test]
+    fn test_rpn_printer() {
+        let printer = RpnPrinter;
+        assert_eq!(printer.print(&parse("(1 + 2) * (4 - 3)")), "1 2 + 4 3 - *");
+        assert_eq!(printer.print(&parse("-123 * (45.67)")), "123 neg 45.67 *");
+        assert_eq!(printer.print(&parse("!true == false")), "true ! false ==");
+        assert_eq!(
+            printer.print(&parse("1 ? 2 : 3 ? 4 : 5")),
+            "1 2 3 4 5 ?: ?:"
+        );
+        assert_eq!(
+            printer.print(&parse("\"a ${1 + 2}\"")),
+            "\"a \" 1 2 + \"\" interpolate(3)"
+        );
+    }
+
+    #[test]
+    fn test_infix_printer() {
+        let printer = InfixPrinter;
+        assert_eq!(
+            printer.print(&parse("\"a${1 + 2 * 3}\\${\"")),
+            "\"a${(1 + (2 * 3))}\\${\""
+        );
+        assert_eq!(printer.print(&parse("1 + 2 * 3")), "(1 + (2 * 3))");
+        assert_eq!(printer.print(&parse("-123 * (45.67)")), "((-123) * 45.67)");
+        assert_eq!(
+            printer.print(&parse("1 < 2 == !nil")),
+            "((1 < 2) == (!nil))"
+        );
+    }
+
+    #[test]
+    fn test_printers_behind_trait() {
+        let expr = parse("(1 + 2) * 3");
+        let printers: Vec<Box<dyn ExprPrinter>> = vec![
+            Box::new(AstPrinter),
+            Box::new(RpnPrinter),
+            Box::new(InfixPrinter),
+        ];
+        let outputs: Vec<String> = printers
+            .iter()
+            .map(|printer| printer.print(&expr))
+            .collect();
+        assert_eq!(
+            outputs,
+            vec!["(* (group (+ 1 2)) 3)", "1 2 + 3 *", "((1 + 2) * 3)"]
+        );
+    }
+
+    #[test]
+    fn test_strings_are_re_escaped() {
+        let expr = parse(r#""a\n" + "say \"\u{48}i\"""#);
+        assert_eq!(AstPrinter.print(&expr), r#"(+ "a\n" "say \"Hi\"")"#);
+    }
+
+    #[test]
+    fn test_numbers_are_printed_as_written() {
+        let expr = parse("0xFF + 1_000 * 2.50e-3 - 1e999");
+        assert_eq!(
+            AstPrinter.print(&expr),
+            "(- (+ 0xFF (* 1_000 2.50e-3)) 1e999)"
+        );
+        assert_eq!(RpnPrinter.print(&expr), "0xFF 1_000 2.50e-3 * + 1e999 -");
+
+        let literals = [1e300, 1e-9, 0.5, f64::INFINITY, f64::NEG_INFINITY, f64::NAN];
+        let printed: Vec<String> = literals
+            .into_iter()
+            .map(|value| {
+                AstPrinter.print(&Expr::Literal(LiteralExpr {
+                    value: TokenLiteral::Number(value),
+                    lexeme: None,
+                    span: Span::default(),
+                }))
+            })
+            .collect();
+        assert_eq!(
+            printed,
+            vec!["1e300", "1e-9", "0.5", "1e999", "-1e999", "(0 / 0)"]
+        );
+    }
 }
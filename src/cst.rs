@@ -0,0 +1,491 @@
+//! Lossless concrete syntax tree.
+//!
+//! Unlike `Expr`, the tree keeps every token of the source, including
+//! parentheses and the `?`/`:` of a conditional, and each token keeps its
+//! trivia, so printing the tree gives back the source byte for byte. Parsing
+//! never fails: unexpected tokens are wrapped in `Error` nodes and missing
+//! ones are marked by empty `Error` nodes, which makes the tree usable by
+//! formatters and refactoring tools while the code is being edited.
+
+use std::{fmt, iter::Peekable, vec};
+
+use crate::{
+    error::LexerError,
+    incremental::{relex, TextEdit},
+    lexer::Lexer,
+    parser::{infix_operator, Associativity, Precedence},
+    token::{LosslessToken, TokenType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The root: an expression followed by the `EOF` token
+    Program,
+    Unary,
+    Binary,
+    Grouping,
+    Conditional,
+    Interpolation,
+    Literal,
+    /// Tokens that do not fit the grammar, or nothing where a token is missing
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode<'a> {
+    pub kind: NodeKind,
+    /// Nodes and tokens in source order
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(LosslessToken<'a>),
+}
+
+impl<'a> SyntaxNode<'a> {
+    /// Lexes and parses `source` into a `Program` node. Lexer errors are not
+    /// printed, and the text they skipped is kept as trivia.
+    pub fn parse(source: &'a str) -> SyntaxNode<'a> {
+        SyntaxNode::parse_with_errors(source).0
+    }
+
+    /// Like `parse`, also returning the lexer errors for the caller to report.
+    /// Parser errors show up as `Error` nodes in the tree.
+    pub fn parse_with_errors(source: &'a str) -> (SyntaxNode<'a>, Vec<LexerError>) {
+        let mut lexer = Lexer::new(source).quiet();
+        let tokens = lexer.scan_lossless();
+        let tree = CstParser {
+            tokens: tokens.into_iter().peekable(),
+        }
+        .program();
+        (tree, lexer.errors().to_vec())
+    }
+
+    /// Parses `new_source`, the result of applying `edit` to the source of
+    /// this tree, reusing as much of the tree as it can. Only the tokens
+    /// around the edit are lexed again, and only the innermost grouping whose
+    /// parentheses are outside them is parsed again. The result is the same
+    /// as `SyntaxNode::parse(new_source)`.
+    pub fn reparse<'s>(&self, edit: &TextEdit, new_source: &'s str) -> SyntaxNode<'s> {
+        let old_tokens: Vec<LosslessToken> = self.tokens().into_iter().cloned().collect();
+        let relexed = relex(&old_tokens, edit, new_source);
+
+        let mut groupings = vec![];
+        self.collect_groupings(&mut vec![], &mut 0, &mut groupings);
+        groupings.sort_by_key(|grouping| grouping.close - grouping.open);
+
+        for grouping in groupings {
+            if grouping.open >= relexed.start || grouping.close < relexed.old_end {
+                continue;
+            }
+
+            // The closing parenthesis is left in so the parser stops at it
+            let close = grouping.close - relexed.old_end + relexed.new_end;
+            let inner = relexed.tokens[grouping.open + 1..=close].to_vec();
+            let mut parser = CstParser {
+                tokens: inner.into_iter().peekable(),
+            };
+            let expression = parser.infix(Precedence::Comma);
+            if parser.tokens.len() != 1 {
+                continue;
+            }
+
+            let skip = close - grouping.open - 1;
+            return self.rebuild(
+                &grouping.path,
+                &mut relexed.tokens.into_iter(),
+                &mut Some((expression, skip)),
+            );
+        }
+
+        CstParser {
+            tokens: relexed.tokens.into_iter().peekable(),
+        }
+        .program()
+    }
+
+    /// Records every grouping closed by a real `)`, with the token indices of
+    /// its parentheses and the path to the expression inside it
+    fn collect_groupings(
+        &self,
+        path: &mut Vec<usize>,
+        next_token: &mut usize,
+        groupings: &mut Vec<Grouping>,
+    ) {
+        let open = *next_token;
+        for (index, child) in self.children.iter().enumerate() {
+            match child {
+                SyntaxElement::Node(node) => {
+                    path.push(index);
+                    node.collect_groupings(path, next_token, groupings);
+                    path.pop();
+                }
+                SyntaxElement::Token(_) => *next_token += 1,
+            }
+        }
+
+        if self.kind == NodeKind::Grouping
+            && matches!(self.children.get(2), Some(SyntaxElement::Token(_)))
+        {
+            let mut path = path.clone();
+            path.push(1);
+            groupings.push(Grouping {
+                path,
+                open,
+                close: *next_token - 1,
+            });
+        }
+    }
+
+    /// The same tree with its tokens taken from `tokens`, and the node at
+    /// `path` replaced by a re-parsed expression that covers `skip` tokens
+    fn rebuild<'s>(
+        &self,
+        path: &[usize],
+        tokens: &mut vec::IntoIter<LosslessToken<'s>>,
+        replacement: &mut Option<(SyntaxNode<'s>, usize)>,
+    ) -> SyntaxNode<'s> {
+        let mut children = vec![];
+        for (index, child) in self.children.iter().enumerate() {
+            let child = match (child, path.split_first()) {
+                (SyntaxElement::Token(_), _) => {
+                    SyntaxElement::Token(tokens.next().expect("as many tokens as before"))
+                }
+                (SyntaxElement::Node(_), Some((&step, []))) if step == index => {
+                    let (expression, skip) = replacement.take().expect("one replacement");
+                    tokens.by_ref().take(skip).for_each(drop);
+                    SyntaxElement::Node(expression)
+                }
+                (SyntaxElement::Node(node), Some((&step, rest))) if step == index => {
+                    SyntaxElement::Node(node.rebuild(rest, tokens, replacement))
+                }
+                (SyntaxElement::Node(node), _) => {
+                    SyntaxElement::Node(node.rebuild(&[], tokens, replacement))
+                }
+            };
+            children.push(child);
+        }
+
+        SyntaxNode {
+            kind: self.kind,
+            children,
+        }
+    }
+
+    /// The tokens under this node, in source order
+    pub fn tokens(&self) -> Vec<&LosslessToken<'a>> {
+        let mut tokens = vec![];
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'n>(&'n self, tokens: &mut Vec<&'n LosslessToken<'a>>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+/// Writes the node's source text, trivia included
+impl fmt::Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            write!(f, "{}", token)?;
+        }
+        Ok(())
+    }
+}
+
+/// A parenthesised grouping in a tree: the token indices of `(` and `)`, and
+/// the child path from the root to the expression between them
+struct Grouping {
+    path: Vec<usize>,
+    open: usize,
+    close: usize,
+}
+
+/// Follows the same grammar as `Parser`, but keeps every token and recovers
+/// from errors instead of reporting them
+struct CstParser<'a> {
+    tokens: Peekable<vec::IntoIter<LosslessToken<'a>>>,
+}
+
+impl<'a> CstParser<'a> {
+    fn program(&mut self) -> SyntaxNode<'a> {
+        let mut children = vec![];
+        if self.peek() != TokenType::EOF {
+            children.push(SyntaxElement::Node(self.infix(Precedence::Comma)));
+        }
+
+        let mut extra = vec![];
+        while self.peek() != TokenType::EOF {
+            extra.push(self.token());
+        }
+        if !extra.is_empty() {
+            children.push(node(NodeKind::Error, extra));
+        }
+
+        children.extend(self.tokens.next().map(SyntaxElement::Token));
+        SyntaxNode {
+            kind: NodeKind::Program,
+            children,
+        }
+    }
+
+    fn infix(&mut self, min_precedence: Precedence) -> SyntaxNode<'a> {
+        let mut expr = self.unary();
+
+        while let Some((precedence, associativity)) = infix_operator(self.peek()) {
+            if precedence < min_precedence {
+                break;
+            }
+
+            let is_conditional = self.peek() == TokenType::Question;
+            let operator = self.token();
+            let right_precedence = match associativity {
+                Associativity::Left => precedence.next(),
+                Associativity::Right => precedence,
+            };
+
+            expr = if is_conditional {
+                let then_branch = self.infix(Precedence::Comma);
+                let colon = self.expect(TokenType::Colon);
+                let else_branch = self.infix(right_precedence);
+                SyntaxNode {
+                    kind: NodeKind::Conditional,
+                    children: vec![
+                        SyntaxElement::Node(expr),
+                        operator,
+                        SyntaxElement::Node(then_branch),
+                        colon,
+                        SyntaxElement::Node(else_branch),
+                    ],
+                }
+            } else {
+                let right = self.infix(right_precedence);
+                SyntaxNode {
+                    kind: NodeKind::Binary,
+                    children: vec![
+                        SyntaxElement::Node(expr),
+                        operator,
+                        SyntaxElement::Node(right),
+                    ],
+                }
+            };
+        }
+
+        expr
+    }
+
+    fn unary(&mut self) -> SyntaxNode<'a> {
+        if matches!(self.peek(), TokenType::Bang | TokenType::Minus) {
+            let operator = self.token();
+            let right = self.unary();
+            return SyntaxNode {
+                kind: NodeKind::Unary,
+                children: vec![operator, SyntaxElement::Node(right)],
+            };
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> SyntaxNode<'a> {
+        match self.peek() {
+            TokenType::False
+            | TokenType::True
+            | TokenType::Nil
+            | TokenType::Number
+            | TokenType::String => SyntaxNode {
+                kind: NodeKind::Literal,
+                children: vec![self.token()],
+            },
+            TokenType::LeftParen => {
+                let left_paren = self.token();
+                let expression = self.infix(Precedence::Comma);
+                let right_paren = self.expect(TokenType::RightParen);
+                SyntaxNode {
+                    kind: NodeKind::Grouping,
+                    children: vec![left_paren, SyntaxElement::Node(expression), right_paren],
+                }
+            }
+            TokenType::Interpolation => {
+                let mut children = vec![self.token()];
+                loop {
+                    children.push(SyntaxElement::Node(self.infix(Precedence::Comma)));
+                    if self.peek() == TokenType::Interpolation {
+                        children.push(self.token());
+                    } else {
+                        children.push(self.expect(TokenType::String));
+                        break;
+                    }
+                }
+                SyntaxNode {
+                    kind: NodeKind::Interpolation,
+                    children,
+                }
+            }
+            // An operator without a left operand keeps its right operand
+            token_type if token_type != TokenType::Question => {
+                if let Some((precedence, _)) = infix_operator(token_type) {
+                    let operator = self.token();
+                    let right = self.infix(precedence.next());
+                    return SyntaxNode {
+                        kind: NodeKind::Error,
+                        children: vec![operator, SyntaxElement::Node(right)],
+                    };
+                }
+                self.unexpected()
+            }
+            _ => self.unexpected(),
+        }
+    }
+
+    /// An `Error` node holding the next token, or an empty one at the end
+    fn unexpected(&mut self) -> SyntaxNode<'a> {
+        let children = match self.peek() {
+            TokenType::EOF => vec![],
+            _ => vec![self.token()],
+        };
+        SyntaxNode {
+            kind: NodeKind::Error,
+            children,
+        }
+    }
+
+    /// The next token if it has type `token_type`, or an empty `Error` node
+    fn expect(&mut self, token_type: TokenType) -> SyntaxElement<'a> {
+        if self.peek() == token_type {
+            return self.token();
+        }
+        node(NodeKind::Error, vec![])
+    }
+
+    fn token(&mut self) -> SyntaxElement<'a> {
+        SyntaxElement::Token(self.tokens.next().expect("tokens end with EOF"))
+    }
+
+    fn peek(&mut self) -> TokenType {
+        self.tokens
+            .peek()
+            .map_or(TokenType::EOF, |token| token.token.token_type)
+    }
+}
+
+fn node<'a>(kind: NodeKind, children: Vec<SyntaxElement<'a>>) -> SyntaxElement<'a> {
+    SyntaxElement::Node(SyntaxNode { kind, children })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cst::{NodeKind, SyntaxElement, SyntaxNode},
+        token::TriviaKind,
+    };
+
+    /// The tree as nested kinds, with tokens written as their lexemes
+    fn shape(node: &SyntaxNode) -> String {
+        let children: Vec<String> = node
+            .children
+            .iter()
+            .map(|child| match child {
+                SyntaxElement::Node(node) => shape(node),
+                SyntaxElement::Token(token) => format!("'{}'", token.token.lexeme),
+            })
+            .collect();
+        format!("{:?}[{}]", node.kind, children.join(" "))
+    }
+
+    #[test]
+    fn test_keeps_every_token() {
+        let tree = SyntaxNode::parse("(1 + 2) ? -x : \"a ${3}\"");
+        assert_eq!(tree.kind, NodeKind::Program);
+        assert_eq!(
+            shape(&tree),
+            "Program[Conditional[Grouping['(' Binary[Literal['1'] '+' Literal['2']] ')'] '?' \
+             Unary['-' Error['x']] ':' Interpolation['\"a ${' Literal['3'] '}\"']] '']"
+        );
+    }
+
+    #[test]
+    fn test_recovers_from_errors() {
+        assert_eq!(
+            shape(&SyntaxNode::parse("(1 +")),
+            "Program[Grouping['(' Binary[Literal['1'] '+' Error[]] Error[]] '']"
+        );
+        assert_eq!(
+            shape(&SyntaxNode::parse("* 2 3")),
+            "Program[Error['*' Literal['2']] Error['3'] '']"
+        );
+        assert_eq!(shape(&SyntaxNode::parse("")), "Program['']");
+    }
+
+    #[test]
+    fn test_returns_lexer_errors() {
+        let (tree, errors) = SyntaxNode::parse_with_errors("1 @ + \"open");
+        assert_eq!(tree.to_string(), "1 @ + \"open");
+        let errors: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|error| (error.column, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![(3, "Unexpected character"), (7, "Unterminated string")]
+        );
+    }
+
+    #[test]
+    fn test_trivia() {
+        let source = "#!/usr/bin/env loxite\n// leading\n1 /* inline */ + 2 // trailing\n\n";
+        let tree = SyntaxNode::parse(source);
+        let tokens = tree.tokens();
+
+        let kinds = |trivia: &[crate::token::Trivia]| -> Vec<TriviaKind> {
+            trivia.iter().map(|trivia| trivia.kind).collect()
+        };
+        assert_eq!(
+            kinds(&tokens[0].leading),
+            vec![
+                TriviaKind::Shebang,
+                TriviaKind::Newline,
+                TriviaKind::LineComment,
+                TriviaKind::Newline
+            ]
+        );
+        assert_eq!(
+            kinds(&tokens[0].trailing),
+            vec![
+                TriviaKind::Whitespace,
+                TriviaKind::BlockComment,
+                TriviaKind::Whitespace
+            ]
+        );
+        assert_eq!(
+            kinds(&tokens[2].trailing),
+            vec![TriviaKind::Whitespace, TriviaKind::LineComment]
+        );
+        assert_eq!(
+            kinds(&tokens[3].leading),
+            vec![TriviaKind::Newline, TriviaKind::Newline]
+        );
+        assert_eq!(tree.to_string(), source);
+    }
+
+    #[test]
+    fn test_round_trips_invalid_source() {
+        let sources = [
+            "1 + @ # 2",
+            "\"unterminated\n",
+            "1 /* unterminated",
+            "\t\r\n  ) ( \"${1 2}\" é",
+            "1 +\r\n  2",
+        ];
+        for source in sources {
+            assert_eq!(SyntaxNode::parse(source).to_string(), source);
+        }
+    }
+}
@@ -0,0 +1,823 @@
+//! A Debug Adapter Protocol server for `loxite dap`, speaking to the editor
+//! over stdio with the same `Content-Length` framing as the language server.
+//!
+//! Programs are single expressions, so the debugger works on operations
+//! instead of statements. It stops before unary and binary operations, which
+//! are the expressions with a line, through the interpreter's `EvalHook`, and
+//! after one when stepping out of it. The
+//! stack has one frame per operation being evaluated, outermost at the
+//! bottom, and each frame's variables are the values of the operations
+//! already evaluated inside it. There are no environments to show, since Lox
+//! expressions have no variables.
+
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    error::LoxiteError,
+    expr::Expr,
+    formatter::Formatter,
+    interpreter::{EvalHook, Interpreter, Value},
+    json::JsonValue,
+    lexer::Lexer,
+    lsp::{read_message, write_message},
+    parser::Parser,
+    token::TokenLiteral,
+};
+
+/// The only thread, as the interpreter is single-threaded
+const THREAD_ID: f64 = 1.0;
+
+/// Runs one debugging session
+pub struct DebugAdapter {
+    session: Rc<RefCell<Session>>,
+}
+
+impl DebugAdapter {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            session: Rc::new(RefCell::new(Session {
+                input: Box::new(input),
+                output: Box::new(output),
+                seq: 0,
+                program: None,
+                configured: false,
+                stop_on_entry: false,
+                paused: false,
+                breakpoints: vec![],
+                step: Step::Continue,
+                frames: vec![],
+                last_line: None,
+                disconnected: false,
+            })),
+        }
+    }
+
+    /// Configures the session, runs the launched program, and answers
+    /// requests until the client disconnects or closes the stream
+    pub fn serve(&mut self) -> io::Result<()> {
+        let Some(program) = self.session.borrow_mut().configure()? else {
+            return Ok(());
+        };
+
+        let exit_code = self.run(&program);
+        let mut session = self.session.borrow_mut();
+        if session.disconnected {
+            return Ok(());
+        }
+        session.event(
+            "exited",
+            JsonValue::object(vec![("exitCode", JsonValue::Number(exit_code as f64))]),
+        )?;
+        session.event("terminated", JsonValue::object(vec![]))?;
+
+        while let Some(request) = session.read()? {
+            if session.handle(&request)? == Handled::Disconnect {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `path`, sending what it prints as output events, and returns the
+    /// exit code `loxite` would have given
+    fn run(&mut self, path: &str) -> i32 {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.output("stderr", &format!("Could not read {}: {}", path, err));
+                return 66;
+            }
+        };
+
+        let expression = match parse(&source) {
+            Ok(expression) => expression,
+            Err(errors) => {
+                for error in errors {
+                    self.output("stderr", &error);
+                }
+                return 65;
+            }
+        };
+
+        {
+            // Before evaluating, so programs without operations stop too
+            let mut session = self.session.borrow_mut();
+            if std::mem::take(&mut session.stop_on_entry) && session.pause("entry").is_err() {
+                session.disconnect();
+            }
+        }
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(Box::new(Hook(self.session.clone())));
+        let result = interpreter.evaluate(&expression);
+        {
+            // An error skips the `after` calls of the operations it aborted,
+            // which would be left on the stack
+            let mut session = self.session.borrow_mut();
+            session.frames.clear();
+            session.last_line = None;
+        }
+        match result {
+            Ok(value) => {
+                self.output("stdout", &value.to_string());
+                0
+            }
+            Err(err) => {
+                self.output("stderr", &err.to_string());
+                70
+            }
+        }
+    }
+
+    fn output(&mut self, category: &str, line: &str) {
+        let mut session = self.session.borrow_mut();
+        if session.disconnected {
+            return;
+        }
+        let body = JsonValue::object(vec![
+            ("category", JsonValue::String(category.to_string())),
+            ("output", JsonValue::String(format!("{}\n", line))),
+        ]);
+        if session.event("output", body).is_err() {
+            session.disconnected = true;
+        }
+    }
+}
+
+/// Where to stop next, besides breakpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Continue,
+    /// At the next operation
+    In,
+    /// At the next operation no deeper than this many frames
+    Over(usize),
+    /// Once there are fewer frames than this, after the operation left
+    Out(usize),
+}
+
+/// An operation being evaluated
+struct Frame {
+    code: String,
+    line: usize,
+    /// The operations evaluated inside it so far, with their values
+    values: Vec<(String, Value)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Handled {
+    /// The request was answered and the session goes on
+    Answered,
+    /// Evaluation may resume
+    Resume,
+    Disconnect,
+}
+
+struct Session {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// Sequence number of the last message sent
+    seq: usize,
+    /// Path of the program to run, from the `launch` request
+    program: Option<String>,
+    /// Set by `configurationDone`; the program starts once it is launched too
+    configured: bool,
+    stop_on_entry: bool,
+    /// Whether the program is stopped, and so may be resumed
+    paused: bool,
+    breakpoints: Vec<usize>,
+    step: Step,
+    frames: Vec<Frame>,
+    /// Line of the last operation reached, so a breakpoint stops once per
+    /// visit to its line
+    last_line: Option<usize>,
+    disconnected: bool,
+}
+
+impl Session {
+    /// Answers requests until the program is both launched and configured,
+    /// and returns its path. `None` if the client left before.
+    fn configure(&mut self) -> io::Result<Option<String>> {
+        while self.program.is_none() || !self.configured {
+            let Some(request) = self.read()? else {
+                return Ok(None);
+            };
+            if self.handle(&request)? == Handled::Disconnect {
+                return Ok(None);
+            }
+        }
+        Ok(self.program.clone())
+    }
+
+    /// Stops at the operation at the top of the stack, if any, answering
+    /// requests until the client resumes
+    fn pause(&mut self, reason: &str) -> io::Result<()> {
+        self.step = Step::Continue;
+        self.paused = true;
+        let result = self.wait(reason);
+        self.paused = false;
+        result
+    }
+
+    /// Sends the stopped event and answers requests until one resumes
+    fn wait(&mut self, reason: &str) -> io::Result<()> {
+        self.event(
+            "stopped",
+            JsonValue::object(vec![
+                ("reason", JsonValue::String(reason.to_string())),
+                ("threadId", JsonValue::Number(THREAD_ID)),
+                ("allThreadsStopped", JsonValue::Boolean(true)),
+            ]),
+        )?;
+
+        while let Some(request) = self.read()? {
+            match self.handle(&request)? {
+                Handled::Answered => {}
+                Handled::Resume => return Ok(()),
+                Handled::Disconnect => break,
+            }
+        }
+        self.disconnect();
+        Ok(())
+    }
+
+    /// Lets the program run to its end without talking to the client
+    fn disconnect(&mut self) {
+        self.disconnected = true;
+        self.breakpoints.clear();
+        self.step = Step::Continue;
+    }
+
+    fn handle(&mut self, request: &JsonValue) -> io::Result<Handled> {
+        let command = request
+            .get("command")
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default();
+        let arguments = request.get("arguments").unwrap_or(&JsonValue::Null);
+
+        let body = match command {
+            "initialize" => {
+                self.respond(request, Ok(capabilities()))?;
+                self.event("initialized", JsonValue::object(vec![]))?;
+                return Ok(Handled::Answered);
+            }
+            "launch" => match arguments.get("program").and_then(JsonValue::as_str) {
+                Some(program) => {
+                    self.program = Some(program.to_string());
+                    self.stop_on_entry = arguments
+                        .get("stopOnEntry")
+                        .and_then(JsonValue::as_bool)
+                        .unwrap_or(false);
+                    Ok(JsonValue::Null)
+                }
+                None => Err("Missing program to launch".to_string()),
+            },
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setExceptionBreakpoints" => Ok(JsonValue::Null),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(JsonValue::Null)
+            }
+            "threads" => Ok(JsonValue::object(vec![(
+                "threads",
+                JsonValue::Array(vec![JsonValue::object(vec![
+                    ("id", JsonValue::Number(THREAD_ID)),
+                    ("name", JsonValue::String("main".to_string())),
+                ])]),
+            )])),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes(arguments)),
+            "variables" => Ok(self.variables(arguments)),
+            "evaluate" => evaluate(arguments),
+            "continue" | "next" | "stepIn" | "stepOut" if self.paused => {
+                let depth = self.frames.len();
+                self.step = match command {
+                    // At entry the first operation is the next one
+                    "next" if depth == 0 => Step::In,
+                    "next" => Step::Over(depth),
+                    "stepIn" => Step::In,
+                    "stepOut" => Step::Out(depth),
+                    _ => Step::Continue,
+                };
+                self.respond(request, Ok(JsonValue::Null))?;
+                return Ok(Handled::Resume);
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(JsonValue::Null))?;
+                return Ok(Handled::Disconnect);
+            }
+            _ => Err(format!("Cannot {} now", command)),
+        };
+
+        self.respond(request, body)?;
+        Ok(Handled::Answered)
+    }
+
+    /// Replaces the breakpoints. They are all verified, as any line may hold
+    /// an operation.
+    fn set_breakpoints(&mut self, arguments: &JsonValue) -> JsonValue {
+        self.breakpoints = arguments
+            .get("breakpoints")
+            .and_then(JsonValue::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|breakpoint| breakpoint.get("line")?.as_f64())
+            .map(|line| line as usize)
+            .collect();
+
+        let breakpoints = self
+            .breakpoints
+            .iter()
+            .map(|line| {
+                JsonValue::object(vec![
+                    ("verified", JsonValue::Boolean(true)),
+                    ("line", JsonValue::Number(*line as f64)),
+                ])
+            })
+            .collect();
+        JsonValue::object(vec![("breakpoints", JsonValue::Array(breakpoints))])
+    }
+
+    /// The frames innermost first. Frame ids count from 1 at the outermost
+    /// operation.
+    fn stack_trace(&self) -> JsonValue {
+        let source = JsonValue::object(vec![(
+            "path",
+            JsonValue::String(self.program.clone().unwrap_or_default()),
+        )]);
+        let frames: Vec<JsonValue> = self
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, frame)| {
+                JsonValue::object(vec![
+                    ("id", JsonValue::Number((index + 1) as f64)),
+                    ("name", JsonValue::String(frame.code.clone())),
+                    ("source", source.clone()),
+                    ("line", JsonValue::Number(frame.line as f64)),
+                    ("column", JsonValue::Number(1.0)),
+                ])
+            })
+            .collect();
+
+        JsonValue::object(vec![
+            ("totalFrames", JsonValue::Number(frames.len() as f64)),
+            ("stackFrames", JsonValue::Array(frames)),
+        ])
+    }
+
+    /// One scope per frame, whose variables reference is the frame id
+    fn scopes(&self, arguments: &JsonValue) -> JsonValue {
+        let frame_id = arguments
+            .get("frameId")
+            .and_then(JsonValue::as_f64)
+            .unwrap_or_default();
+        JsonValue::object(vec![(
+            "scopes",
+            JsonValue::Array(vec![JsonValue::object(vec![
+                ("name", JsonValue::String("Values".to_string())),
+                ("variablesReference", JsonValue::Number(frame_id)),
+                ("expensive", JsonValue::Boolean(false)),
+            ])]),
+        )])
+    }
+
+    fn variables(&self, arguments: &JsonValue) -> JsonValue {
+        let reference = arguments
+            .get("variablesReference")
+            .and_then(JsonValue::as_f64)
+            .unwrap_or_default() as usize;
+        let values = match reference
+            .checked_sub(1)
+            .and_then(|index| self.frames.get(index))
+        {
+            Some(frame) => frame
+                .values
+                .iter()
+                .map(|(code, value)| {
+                    JsonValue::object(vec![
+                        ("name", JsonValue::String(code.clone())),
+                        ("value", JsonValue::String(show(value))),
+                        ("type", JsonValue::String(type_name(value).to_string())),
+                        ("variablesReference", JsonValue::Number(0.0)),
+                    ])
+                })
+                .collect(),
+            None => vec![],
+        };
+        JsonValue::object(vec![("variables", JsonValue::Array(values))])
+    }
+
+    fn read(&mut self) -> io::Result<Option<JsonValue>> {
+        loop {
+            let Some(body) = read_message(&mut self.input)? else {
+                return Ok(None);
+            };
+            // Messages that are not JSON have no seq to answer to
+            if let Ok(request) = JsonValue::parse(&body) {
+                return Ok(Some(request));
+            }
+        }
+    }
+
+    fn respond(&mut self, request: &JsonValue, body: Result<JsonValue, String>) -> io::Result<()> {
+        let mut members = vec![
+            ("type", JsonValue::String("response".to_string())),
+            (
+                "request_seq",
+                request.get("seq").cloned().unwrap_or(JsonValue::Null),
+            ),
+            (
+                "command",
+                request.get("command").cloned().unwrap_or(JsonValue::Null),
+            ),
+        ];
+        match body {
+            Ok(JsonValue::Null) => members.push(("success", JsonValue::Boolean(true))),
+            Ok(body) => {
+                members.push(("success", JsonValue::Boolean(true)));
+                members.push(("body", body));
+            }
+            Err(message) => {
+                members.push(("success", JsonValue::Boolean(false)));
+                members.push(("message", JsonValue::String(message)));
+            }
+        }
+        self.send(members)
+    }
+
+    fn event(&mut self, event: &str, body: JsonValue) -> io::Result<()> {
+        self.send(vec![
+            ("type", JsonValue::String("event".to_string())),
+            ("event", JsonValue::String(event.to_string())),
+            ("body", body),
+        ])
+    }
+
+    /// Sends a message with the next sequence number
+    fn send(&mut self, members: Vec<(&str, JsonValue)>) -> io::Result<()> {
+        self.seq += 1;
+        let mut message = vec![("seq", JsonValue::Number(self.seq as f64))];
+        message.extend(members);
+        write_message(&mut self.output, &JsonValue::object(message))?;
+        self.output.flush()
+    }
+}
+
+/// Shares the session with the interpreter, which calls it before and after
+/// each operation
+struct Hook(Rc<RefCell<Session>>);
+
+impl EvalHook for Hook {
+    fn before(&mut self, expr: &Expr) {
+        let line = match expr {
+            Expr::Unary(unary) => unary.operator.line,
+            Expr::Binary(binary) => binary.operator.line,
+            _ => return,
+        };
+
+        let mut session = self.0.borrow_mut();
+        session.frames.push(Frame {
+            code: Formatter.format_expr(expr),
+            line,
+            values: vec![],
+        });
+
+        let depth = session.frames.len();
+        let stepped = match session.step {
+            Step::Continue => false,
+            Step::In => true,
+            Step::Over(frames) => depth <= frames,
+            // An operation is only left in `after`
+            Step::Out(_) => false,
+        };
+        let reason = if stepped {
+            Some("step")
+        } else if session.breakpoints.contains(&line) && session.last_line != Some(line) {
+            Some("breakpoint")
+        } else {
+            None
+        };
+        session.last_line = Some(line);
+
+        if let Some(reason) = reason {
+            if session.pause(reason).is_err() {
+                session.disconnect();
+            }
+        }
+    }
+
+    /// Stepping out stops here, in the operation that was waiting for the
+    /// value, which is then among its variables
+    fn after(&mut self, _expr: &Expr, value: &Value) {
+        let mut session = self.0.borrow_mut();
+        let Some(frame) = session.frames.pop() else {
+            return;
+        };
+        if let Some(parent) = session.frames.last_mut() {
+            parent.values.push((frame.code, value.clone()));
+        }
+
+        let depth = session.frames.len();
+        if matches!(session.step, Step::Out(frames) if depth < frames)
+            && depth > 0
+            && session.pause("step").is_err()
+        {
+            session.disconnect();
+        }
+    }
+}
+
+fn capabilities() -> JsonValue {
+    JsonValue::object(vec![
+        ("supportsConfigurationDoneRequest", JsonValue::Boolean(true)),
+        ("supportsEvaluateForHovers", JsonValue::Boolean(true)),
+        ("supportsTerminateRequest", JsonValue::Boolean(true)),
+    ])
+}
+
+/// Evaluates the expression typed by the user. Having no variables, it does
+/// not depend on the paused frame.
+fn evaluate(arguments: &JsonValue) -> Result<JsonValue, String> {
+    let source = arguments
+        .get("expression")
+        .and_then(JsonValue::as_str)
+        .ok_or("Missing expression")?;
+    let expression = parse(source).map_err(|errors| errors.join("\n"))?;
+    let value = Interpreter::new()
+        .evaluate(&expression)
+        .map_err(|err| err.to_string())?;
+
+    Ok(JsonValue::object(vec![
+        ("result", JsonValue::String(show(&value))),
+        ("type", JsonValue::String(type_name(&value).to_string())),
+        ("variablesReference", JsonValue::Number(0.0)),
+    ]))
+}
+
+/// Lexes and parses `source`, returning the error messages if it fails
+fn parse(source: &str) -> Result<Expr<'_>, Vec<String>> {
+    let mut lexer = Lexer::new(source).quiet();
+    let tokens = lexer.scan_tokens();
+    if lexer.had_error {
+        return Err(lexer
+            .errors()
+            .iter()
+            .map(|error| LoxiteError::Lexer(error.clone()).to_string())
+            .collect());
+    }
+
+    let mut parser = Parser::new(tokens).quiet();
+    parser.parser().ok_or_else(|| {
+        parser
+            .errors()
+            .iter()
+            .map(|error| LoxiteError::Parser(error.clone()).to_string())
+            .collect()
+    })
+}
+
+/// A value as Lox source, so strings show their quotes
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(string) => TokenLiteral::String(string.clone()).to_source(),
+        value => value.to_string(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Boolean(_) => "boolean",
+        Value::Nil => "nil",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        env, fs,
+        io::{self, BufReader, Write},
+        path::PathBuf,
+        process,
+        rc::Rc,
+    };
+
+    use crate::{dap::DebugAdapter, json::JsonValue, lsp::read_message};
+
+    /// Output the test can read after the adapter is done with it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs a session over a scripted transcript of requests, each given as
+    /// `command arguments`, and returns the messages sent back, without their
+    /// sequence numbers
+    fn transcript(requests: &[(&str, &str)]) -> Vec<String> {
+        let input: String = requests
+            .iter()
+            .enumerate()
+            .map(|(index, (command, arguments))| {
+                let message = format!(
+                    r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#,
+                    index + 1,
+                    command,
+                    arguments
+                );
+                format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+            })
+            .collect();
+        let output = Shared::default();
+        DebugAdapter::new(io::Cursor::new(input.into_bytes()), output.clone())
+            .serve()
+            .unwrap();
+
+        let bytes = output.0.borrow();
+        let mut reader = BufReader::new(bytes.as_slice());
+        let mut messages = vec![];
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            match JsonValue::parse(&body).unwrap() {
+                JsonValue::Object(mut members) => {
+                    members.retain(|(key, _)| key != "seq");
+                    messages.push(JsonValue::Object(members).to_string());
+                }
+                message => panic!("Unexpected message {}", message),
+            }
+        }
+        messages
+    }
+
+    /// A program written to a file named after the process and the test, so
+    /// concurrent runs do not share it; removed when dropped
+    struct Program(PathBuf);
+
+    impl Program {
+        fn new(test: &str, source: &str) -> Program {
+            let path = env::temp_dir().join(format!("loxite_dap_{}_{}.lox", process::id(), test));
+            fs::write(&path, source).unwrap();
+            Program(path)
+        }
+
+        /// The path as a JSON string
+        fn json(&self) -> String {
+            JsonValue::String(self.0.to_string_lossy().into_owned()).to_string()
+        }
+    }
+
+    impl Drop for Program {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let program = Program::new("stepping", "(1 + 2) *\n  (3 - 4) -\n  -5\n");
+        let path = program.json();
+        let launch = format!(r#"{{"program":{}}}"#, path);
+        let breakpoints = format!(
+            r#"{{"source":{{"path":{}}},"breakpoints":[{{"line":1}}]}}"#,
+            path
+        );
+        let messages = transcript(&[
+            ("initialize", r#"{"adapterID":"loxite"}"#),
+            ("launch", &launch),
+            ("setBreakpoints", &breakpoints),
+            ("configurationDone", "{}"),
+            ("stackTrace", r#"{"threadId":1}"#),
+            ("next", r#"{"threadId":1}"#),
+            ("stackTrace", r#"{"threadId":1}"#),
+            ("variables", r#"{"variablesReference":1}"#),
+            ("evaluate", r#"{"expression":"\"a\" + \"b\"","frameId":2}"#),
+            ("evaluate", r#"{"expression":"-nil"}"#),
+            ("stepIn", r#"{"threadId":1}"#),
+            ("disconnect", "{}"),
+        ]);
+
+        let expected = [
+            r#"{"type":"response","request_seq":1,"command":"initialize","success":true,"body":{"supportsConfigurationDoneRequest":true,"supportsEvaluateForHovers":true,"supportsTerminateRequest":true}}"#,
+            r#"{"type":"event","event":"initialized","body":{}}"#,
+            r#"{"type":"response","request_seq":2,"command":"launch","success":true}"#,
+            r#"{"type":"response","request_seq":3,"command":"setBreakpoints","success":true,"body":{"breakpoints":[{"verified":true,"line":1}]}}"#,
+            r#"{"type":"response","request_seq":4,"command":"configurationDone","success":true}"#,
+            r#"{"type":"event","event":"stopped","body":{"reason":"breakpoint","threadId":1,"allThreadsStopped":true}}"#,
+            r#"{"type":"response","request_seq":5,"command":"stackTrace","success":true,"body":{"totalFrames":2,"stackFrames":[{"id":2,"name":"(1 + 2) * (3 - 4)","source":{"path":PATH},"line":1,"column":1},{"id":1,"name":"(1 + 2) * (3 - 4) - -5","source":{"path":PATH},"line":2,"column":1}]}}"#,
+            r#"{"type":"response","request_seq":6,"command":"next","success":true}"#,
+            r#"{"type":"event","event":"stopped","body":{"reason":"step","threadId":1,"allThreadsStopped":true}}"#,
+            r#"{"type":"response","request_seq":7,"command":"stackTrace","success":true,"body":{"totalFrames":2,"stackFrames":[{"id":2,"name":"-5","source":{"path":PATH},"line":3,"column":1},{"id":1,"name":"(1 + 2) * (3 - 4) - -5","source":{"path":PATH},"line":2,"column":1}]}}"#,
+            r#"{"type":"response","request_seq":8,"command":"variables","success":true,"body":{"variables":[{"name":"(1 + 2) * (3 - 4)","value":"-3","type":"number","variablesReference":0}]}}"#,
+            r#"{"type":"response","request_seq":9,"command":"evaluate","success":true,"body":{"result":"\"ab\"","type":"string","variablesReference":0}}"#,
+            r#"{"type":"response","request_seq":10,"command":"evaluate","success":false,"message":"Runtime Error: [line 1]: Operand must be a number."}"#,
+            r#"{"type":"response","request_seq":11,"command":"stepIn","success":true}"#,
+            r#"{"type":"event","event":"output","body":{"category":"stdout","output":"2\n"}}"#,
+            r#"{"type":"event","event":"exited","body":{"exitCode":0}}"#,
+            r#"{"type":"event","event":"terminated","body":{}}"#,
+            r#"{"type":"response","request_seq":12,"command":"disconnect","success":true}"#,
+        ];
+        assert_eq!(
+            messages,
+            expected
+                .iter()
+                .map(|message| message.replace("PATH", &path))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_step_out() {
+        let program = Program::new("step_out", "(1 + 2) *\n  (3 - 4) -\n  -5\n");
+        let path = program.json();
+        let launch = format!(r#"{{"program":{}}}"#, path);
+        let breakpoints = format!(
+            r#"{{"source":{{"path":{}}},"breakpoints":[{{"line":1}}]}}"#,
+            path
+        );
+        let messages = transcript(&[
+            ("launch", &launch),
+            ("setBreakpoints", &breakpoints),
+            ("configurationDone", "{}"),
+            ("stepOut", r#"{"threadId":1}"#),
+            ("stackTrace", r#"{"threadId":1}"#),
+            ("variables", r#"{"variablesReference":1}"#),
+            ("stepOut", r#"{"threadId":1}"#),
+        ]);
+        assert_eq!(
+            messages[3..],
+            [
+                r#"{"type":"event","event":"stopped","body":{"reason":"breakpoint","threadId":1,"allThreadsStopped":true}}"#,
+                r#"{"type":"response","request_seq":4,"command":"stepOut","success":true}"#,
+                r#"{"type":"event","event":"stopped","body":{"reason":"step","threadId":1,"allThreadsStopped":true}}"#,
+                &r#"{"type":"response","request_seq":5,"command":"stackTrace","success":true,"body":{"totalFrames":1,"stackFrames":[{"id":1,"name":"(1 + 2) * (3 - 4) - -5","source":{"path":PATH},"line":2,"column":1}]}}"#.replace("PATH", &path),
+                r#"{"type":"response","request_seq":6,"command":"variables","success":true,"body":{"variables":[{"name":"(1 + 2) * (3 - 4)","value":"-3","type":"number","variablesReference":0}]}}"#,
+                r#"{"type":"response","request_seq":7,"command":"stepOut","success":true}"#,
+                r#"{"type":"event","event":"output","body":{"category":"stdout","output":"2\n"}}"#,
+                r#"{"type":"event","event":"exited","body":{"exitCode":0}}"#,
+                r#"{"type":"event","event":"terminated","body":{}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stop_on_entry_without_operations() {
+        let program = Program::new("entry", "1");
+        let launch = format!(r#"{{"program":{},"stopOnEntry":true}}"#, program.json());
+        let messages = transcript(&[
+            ("launch", &launch),
+            ("configurationDone", "{}"),
+            ("stackTrace", r#"{"threadId":1}"#),
+            ("continue", r#"{"threadId":1}"#),
+        ]);
+        assert_eq!(
+            messages[2..],
+            [
+                r#"{"type":"event","event":"stopped","body":{"reason":"entry","threadId":1,"allThreadsStopped":true}}"#,
+                r#"{"type":"response","request_seq":3,"command":"stackTrace","success":true,"body":{"totalFrames":0,"stackFrames":[]}}"#,
+                r#"{"type":"response","request_seq":4,"command":"continue","success":true}"#,
+                r#"{"type":"event","event":"output","body":{"category":"stdout","output":"1\n"}}"#,
+                r#"{"type":"event","event":"exited","body":{"exitCode":0}}"#,
+                r#"{"type":"event","event":"terminated","body":{}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stop_on_entry_and_runtime_error() {
+        let program = Program::new("runtime_error", "1 + -\"a\"");
+        let path = program.json();
+        let launch = format!(r#"{{"program":{},"stopOnEntry":true}}"#, path);
+        let messages = transcript(&[
+            ("launch", &launch),
+            ("configurationDone", "{}"),
+            ("stepOut", r#"{"threadId":1}"#),
+            ("stackTrace", r#"{"threadId":1}"#),
+        ]);
+        assert_eq!(
+            messages[2..],
+            [
+                r#"{"type":"event","event":"stopped","body":{"reason":"entry","threadId":1,"allThreadsStopped":true}}"#,
+                r#"{"type":"response","request_seq":3,"command":"stepOut","success":true}"#,
+                r#"{"type":"event","event":"output","body":{"category":"stderr","output":"Runtime Error: [line 1]: Operand must be a number.\n"}}"#,
+                r#"{"type":"event","event":"exited","body":{"exitCode":70}}"#,
+                r#"{"type":"event","event":"terminated","body":{}}"#,
+                r#"{"type":"response","request_seq":4,"command":"stackTrace","success":true,"body":{"totalFrames":0,"stackFrames":[]}}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    ast_printer::ExprPrinter,
+    expr::{
+        BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+    },
+    token::TokenLiteral,
+    visitor::Visitor,
+};
+
+/// Graphviz DOT output, one node per expression with labelled edges to its
+/// operands. Render it with `dot -Tsvg`.
+pub struct DotPrinter;
+
+impl ExprPrinter for DotPrinter {
+    fn print(&self, expr: &Expr) -> String {
+        let mut graph = DotGraph {
+            output: String::from("digraph ast {\n    node [shape=box];\n"),
+            next_id: 0,
+        };
+        graph.visit_expr(expr);
+        graph.output.push_str("}\n");
+        graph.output
+    }
+}
+
+/// Visiting an expression adds it and its children to the graph, returning
+/// the id of its node
+struct DotGraph {
+    output: String,
+    next_id: usize,
+}
+
+impl DotGraph {
+    fn add_node(&mut self, label: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.output
+            .push_str(&format!("    node{} [label=\"{}\"];\n", id, escape(label)));
+        id
+    }
+
+    fn add_edge(&mut self, parent: usize, child: &Expr, label: &str) {
+        let child = self.visit_expr(child);
+        self.link(parent, child, label);
+    }
+
+    fn link(&mut self, parent: usize, child: usize, label: &str) {
+        self.output.push_str(&format!(
+            "    node{} -> node{} [label=\"{}\"];\n",
+            parent, child, label
+        ));
+    }
+}
+
+impl Visitor<usize> for DotGraph {
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> usize {
+        let id = self.add_node(&format!("Unary {}", expr.operator.lexeme));
+        self.add_edge(id, &expr.right, "operand");
+        id
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> usize {
+        let id = self.add_node(&format!("Binary {}", expr.operator.lexeme));
+        self.add_edge(id, &expr.left, "left");
+        self.add_edge(id, &expr.right, "right");
+        id
+    }
+
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> usize {
+        let id = self.add_node("Grouping");
+        self.add_edge(id, &expr.expression, "expression");
+        id
+    }
+
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> usize {
+        let id = self.add_node("Conditional");
+        self.add_edge(id, &expr.condition, "condition");
+        self.add_edge(id, &expr.then_branch, "then");
+        self.add_edge(id, &expr.else_branch, "else");
+        id
+    }
+
+    /// The text segments hang off the node as string literals between the
+    /// embedded expressions
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> usize {
+        let id = self.add_node("Interpolation");
+        for (index, string) in expr.strings.iter().enumerate() {
+            let text = TokenLiteral::String(string.clone()).to_source();
+            let text_id = self.add_node(&format!("Literal {}", text));
+            self.link(id, text_id, "text");
+            if let Some(expression) = expr.expressions.get(index) {
+                self.add_edge(id, expression, "expression");
+            }
+        }
+        id
+    }
+
+    fn visit_literal(&mut self, expr: &LiteralExpr) -> usize {
+        self.add_node(&format!("Literal {}", expr.to_source()))
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast_printer::ExprPrinter, dot_printer::DotPrinter, lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn test_dot_output() {
+        let tokens = Lexer::new("-123 * (\"a\")").scan_tokens();
+        let expr = Parser::new(tokens).parser().unwrap();
+
+        let expected = r#"digraph ast {
+    node [shape=box];
+    node0 [label="Binary *"];
+    node1 [label="Unary -"];
+    node2 [label="Literal 123"];
+    node1 -> node2 [label="operand"];
+    node0 -> node1 [label="left"];
+    node3 [label="Grouping"];
+    node4 [label="Literal \"a\""];
+    node3 -> node4 [label="expression"];
+    node0 -> node3 [label="right"];
+}
+"#;
+        assert_eq!(DotPrinter.print(&expr), expected);
+    }
+}
@@ -7,42 +7,51 @@ use crate::token::{Token, TokenType};
 
 pub type Result<T> = std::result::Result<T, LoxiteError>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParserError {
-    pub token: Token,
+    pub token: Token<'static>,
     pub message: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LexerError {
     pub line: usize,
+    /// 1-based, counted in characters from the start of the line
+    pub column: usize,
     pub message: String,
 }
 
 #[derive(Debug)]
 pub struct RuntimeError {
-    pub token: Token,
+    pub token: Token<'static>,
     pub message: String,
 }
 
 impl RuntimeError {
+    pub fn number_operand(token: &Token) -> Self {
+        Self {
+            token: token.clone().into_owned(),
+            message: String::from("Operand must be a number."),
+        }
+    }
+
     pub fn number_operands(token: &Token) -> Self {
         Self {
-            token: token.clone(),
+            token: token.clone().into_owned(),
             message: String::from("Operands must be numbers."),
         }
     }
 
     pub fn number_or_string_operands(token: &Token) -> Self {
         Self {
-            token: token.clone(),
+            token: token.clone().into_owned(),
             message: String::from("Operands must be two integers or two strings."),
         }
     }
 
     pub fn error(token: &Token, msg: &str) -> Self {
         Self {
-            token: token.clone(),
+            token: token.clone().into_owned(),
             message: msg.to_string(),
         }
     }
@@ -60,8 +69,8 @@ impl fmt::Display for LoxiteError {
         match self {
             LoxiteError::Lexer(err) => write!(
                 f,
-                "Lexer error: [line {}] Error{}: {}",
-                err.line, "", err.message
+                "Lexer error: [line {}] Error at column {}: {}",
+                err.line, err.column, err.message
             ),
             LoxiteError::Parser(err) => {
                 let location = match err.token.token_type {
@@ -1,27 +1,105 @@
-use crate::token::{Token, TokenLiteral};
+use crate::token::{escape_string, Span, Token, TokenLiteral};
 
-pub enum Expr {
-    Unary(Box<UnaryExpr>),
-    Binary(Box<BinaryExpr>),
-    Grouping(Box<GroupingExpr>),
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr<'a> {
+    Unary(Box<UnaryExpr<'a>>),
+    Binary(Box<BinaryExpr<'a>>),
+    Grouping(Box<GroupingExpr<'a>>),
+    Conditional(Box<ConditionalExpr<'a>>),
+    Interpolation(Box<InterpolationExpr<'a>>),
     Literal(LiteralExpr),
 }
 
-pub struct UnaryExpr {
-    pub operator: Token,
-    pub right: Expr,
+impl Expr<'_> {
+    /// Where the expression sits in the source. Nodes made by code rather
+    /// than parsed have an empty span at 0.
+    pub fn span(&self) -> Span {
+        let between = |start: Span, end: Span| Span {
+            start: start.start,
+            end: end.end,
+        };
+        match self {
+            Expr::Unary(expr) => between(expr.operator.span, expr.right.span()),
+            Expr::Binary(expr) => between(expr.left.span(), expr.right.span()),
+            Expr::Grouping(expr) => expr.span,
+            Expr::Conditional(expr) => between(expr.condition.span(), expr.else_branch.span()),
+            Expr::Interpolation(expr) => expr.span,
+            Expr::Literal(expr) => expr.span,
+        }
+    }
 }
 
-pub struct BinaryExpr {
-    pub left: Expr,
-    pub operator: Token,
-    pub right: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnaryExpr<'a> {
+    pub operator: Token<'a>,
+    pub right: Expr<'a>,
 }
 
-pub struct GroupingExpr {
-    pub expression: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryExpr<'a> {
+    pub left: Expr<'a>,
+    pub operator: Token<'a>,
+    pub right: Expr<'a>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupingExpr<'a> {
+    pub expression: Expr<'a>,
+    /// From `(` to `)`
+    pub span: Span,
+}
+
+/// `condition ? then_branch : else_branch`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalExpr<'a> {
+    pub condition: Expr<'a>,
+    pub then_branch: Expr<'a>,
+    pub else_branch: Expr<'a>,
+}
+
+/// `"text ${expression} text"`. The text around the embedded expressions is
+/// kept in `strings`, which always has one more element than `expressions`
+/// since the string starts and ends with text, even if it is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpolationExpr<'a> {
+    pub strings: Vec<String>,
+    pub expressions: Vec<Expr<'a>>,
+    /// From the opening quote to the closing one
+    pub span: Span,
+}
+
+impl InterpolationExpr<'_> {
+    /// Writes the string back as Lox source, with each embedded expression
+    /// rendered by `embed`
+    pub fn to_source(&self, mut embed: impl FnMut(&Expr) -> String) -> String {
+        let mut source = format!("\"{}", escape_string(&self.strings[0]));
+        for (expression, string) in self.expressions.iter().zip(&self.strings[1..]) {
+            source.push_str(&format!(
+                "${{{}}}{}",
+                embed(expression),
+                escape_string(string)
+            ));
+        }
+        source.push('"');
+        source
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LiteralExpr {
     pub value: TokenLiteral,
+    /// How a number was written, e.g. `0xFF` or `1_000`, so it is printed back
+    /// unchanged. `None` for other literals and for numbers made by code.
+    pub lexeme: Option<String>,
+    pub span: Span,
+}
+
+impl LiteralExpr {
+    /// Writes the literal back as Lox source, numbers as they were written
+    pub fn to_source(&self) -> String {
+        match &self.lexeme {
+            Some(lexeme) => lexeme.clone(),
+            None => self.value.to_source(),
+        }
+    }
 }
@@ -0,0 +1,215 @@
+use crate::{
+    expr::{
+        BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+    },
+    lexer::Lexer,
+    parser::Parser,
+    token::{Comment, TokenType},
+    visitor::Visitor,
+};
+
+/// Turns Lox source into its canonical layout: one space around binary
+/// operators, none after unary operators or inside parentheses.
+///
+/// Comments are kept on their own lines before the expression, except a
+/// comment following the expression on its last line, which stays at its end.
+/// Comments from inside a multi-line expression are moved above it, and runs
+/// of blank lines collapse into one.
+pub struct Formatter;
+
+impl Formatter {
+    /// Formats a whole program. Returns `None` when the source does not lex or
+    /// parse, after the errors have been reported.
+    pub fn format(&self, source: &str) -> Option<String> {
+        self.format_source(source, false)
+    }
+
+    /// Like `format`, but without reporting the errors
+    pub fn format_quietly(&self, source: &str) -> Option<String> {
+        self.format_source(source, true)
+    }
+
+    fn format_source(&self, source: &str, quiet: bool) -> Option<String> {
+        let mut lexer = Lexer::new(source);
+        if quiet {
+            lexer = lexer.quiet();
+        }
+        let tokens = lexer.scan_tokens();
+        if lexer.had_error {
+            return None;
+        }
+
+        let first_line = tokens.first()?.line;
+        let last_token = tokens
+            .iter()
+            .rev()
+            .find(|token| token.token_type != TokenType::EOF)
+            .map(|token| (token.line, token.span.end));
+
+        let mut parser = Parser::new(tokens);
+        if quiet {
+            parser = parser.quiet();
+        }
+        let expression = parser.parser()?;
+        let (last_line, code_end) = last_token?;
+
+        let mut lines = Lines::default();
+        if source.starts_with("#!") {
+            let shebang = source.lines().next().unwrap_or_default();
+            lines.push(1, 1, shebang.to_string());
+        }
+
+        let (before, after): (Vec<&Comment>, Vec<&Comment>) = lexer
+            .comments()
+            .iter()
+            .partition(|comment| comment.span.start < code_end);
+        for comment in before {
+            if comment.line < first_line {
+                lines.push(comment.line, comment.end_line(), comment.text.clone());
+            } else {
+                lines.push(first_line, first_line, comment.text.clone());
+            }
+        }
+
+        let mut code = self.format_expr(&expression);
+        let mut code_last_line = last_line;
+        let mut after = after.into_iter().peekable();
+        if let Some(comment) = after.next_if(|comment| comment.line == last_line) {
+            code.push(' ');
+            code.push_str(&comment.text);
+            code_last_line = comment.end_line();
+        }
+        lines.push(first_line, code_last_line, code);
+        for comment in after {
+            lines.push(comment.line, comment.end_line(), comment.text.clone());
+        }
+
+        Some(lines.output)
+    }
+
+    pub fn format_expr(&self, expr: &Expr) -> String {
+        Formatter.visit_expr(expr)
+    }
+}
+
+impl Visitor<String> for Formatter {
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> String {
+        format!("{}{}", expr.operator.lexeme, self.visit_expr(&expr.right))
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> String {
+        let left = self.visit_expr(&expr.left);
+        let right = self.visit_expr(&expr.right);
+        match expr.operator.token_type {
+            TokenType::Comma => format!("{}, {}", left, right),
+            _ => format!("{} {} {}", left, expr.operator.lexeme, right),
+        }
+    }
+
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> String {
+        format!("({})", self.visit_expr(&expr.expression))
+    }
+
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> String {
+        format!(
+            "{} ? {} : {}",
+            self.visit_expr(&expr.condition),
+            self.visit_expr(&expr.then_branch),
+            self.visit_expr(&expr.else_branch)
+        )
+    }
+
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> String {
+        expr.to_source(|expression| self.visit_expr(expression))
+    }
+
+    fn visit_literal(&mut self, expr: &LiteralExpr) -> String {
+        expr.to_source()
+    }
+}
+
+/// Output lines, keeping a single blank line wherever the source had one or more.
+#[derive(Default)]
+struct Lines {
+    output: String,
+    last_line: Option<usize>,
+}
+
+impl Lines {
+    /// Adds `text`, which came from source lines `first_line..=last_line`
+    fn push(&mut self, first_line: usize, last_line: usize, text: String) {
+        if let Some(previous) = self.last_line {
+            if first_line > previous + 1 {
+                self.output.push('\n');
+            }
+        }
+        self.output.push_str(&text);
+        self.output.push('\n');
+        self.last_line = Some(last_line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formatter::Formatter;
+
+    #[test]
+    fn test_canonical_spacing() {
+        assert_eq!(
+            Formatter.format("(1+2)*-  3==  !true").unwrap(),
+            "(1 + 2) * -3 == !true\n"
+        );
+        assert_eq!(
+            Formatter.format("1?2:3 ?4:5").unwrap(),
+            "1 ? 2 : 3 ? 4 : 5\n"
+        );
+        assert_eq!(Formatter.format("1 ,2 ,3").unwrap(), "1, 2, 3\n");
+        assert_eq!(Formatter.format("\"a\"  +nil").unwrap(), "\"a\" + nil\n");
+        assert_eq!(
+            Formatter.format("\"x=${ 1+2 }, \\${y}\"").unwrap(),
+            "\"x=${1 + 2}, \\${y}\"\n"
+        );
+    }
+
+    #[test]
+    fn test_numbers_keep_their_spelling() {
+        assert_eq!(
+            Formatter.format("0xFF+1_000_000+ 1e-9 +1e300").unwrap(),
+            "0xFF + 1_000_000 + 1e-9 + 1e300\n"
+        );
+        assert_eq!(
+            Formatter.format("-1e999 *0b1_0 /0o17").unwrap(),
+            "-1e999 * 0b1_0 / 0o17\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_comments() {
+        let source =
+            "#!/usr/bin/env loxite\n// header\n\n\n1 +\n  // inside\n  2 // end\n// footer\n";
+        let expected = "#!/usr/bin/env loxite\n// header\n\n// inside\n1 + 2 // end\n// footer\n";
+        assert_eq!(Formatter.format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_keeps_block_comments() {
+        let source = "/* license\n   text */\n\n/* a */ 1 + /* b */ 2 /* c */\n/* d\n*/\n";
+        let expected = "/* license\n   text */\n\n/* a */\n/* b */\n1 + 2 /* c */\n/* d\n*/\n";
+        assert_eq!(Formatter.format(source).unwrap(), expected);
+        assert_eq!(Formatter.format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_formatting_is_idempotent() {
+        let source = "// a\n1 +\n// b\n2\n\n// c\n";
+        let once = Formatter.format(source).unwrap();
+        assert_eq!(Formatter.format(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn test_invalid_source_is_not_formatted() {
+        assert!(Formatter.format("1 +").is_none());
+        assert!(Formatter.format("1 2 + 3\n").is_none());
+        assert!(Formatter.format("1 + 2 // a\n/* b */ 3").is_none());
+    }
+}
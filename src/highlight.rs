@@ -0,0 +1,265 @@
+//! Semantic highlighting: every piece of a source, trivia included, gets a
+//! category that the terminal and HTML outputs colour the same way.
+//!
+//! Identifiers all fall in one category, since there is no resolver to tell
+//! locals, globals, parameters, classes and methods apart.
+
+use crate::{
+    lexer::Lexer,
+    token::{LosslessToken, TokenType, TriviaKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Keyword,
+    /// `true`, `false` and `nil`
+    Constant,
+    Number,
+    String,
+    Operator,
+    /// Parentheses, braces, `;`, `.`, and the `${`/`}` around an embedded
+    /// expression
+    Punctuation,
+    Identifier,
+    /// Comments and the `#!` line
+    Comment,
+    /// Text the lexer skipped
+    Error,
+}
+
+impl Highlight {
+    /// The category of a token, `None` for `EOF`
+    pub fn of_token(token_type: TokenType) -> Option<Highlight> {
+        let highlight = match token_type {
+            TokenType::LeftParen
+            | TokenType::RightParen
+            | TokenType::LeftBrace
+            | TokenType::RightBrace
+            | TokenType::Dot
+            | TokenType::Semicolon => Highlight::Punctuation,
+            TokenType::Comma
+            | TokenType::Minus
+            | TokenType::Plus
+            | TokenType::Slash
+            | TokenType::Star
+            | TokenType::Question
+            | TokenType::Colon
+            | TokenType::Bang
+            | TokenType::BangEqual
+            | TokenType::Equal
+            | TokenType::EqualEqual
+            | TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => Highlight::Operator,
+            TokenType::String | TokenType::Interpolation => Highlight::String,
+            TokenType::Number => Highlight::Number,
+            TokenType::Identifier => Highlight::Identifier,
+            TokenType::False | TokenType::Nil | TokenType::True => Highlight::Constant,
+            TokenType::And
+            | TokenType::Class
+            | TokenType::Else
+            | TokenType::Fun
+            | TokenType::For
+            | TokenType::If
+            | TokenType::Or
+            | TokenType::Print
+            | TokenType::Return
+            | TokenType::Super
+            | TokenType::This
+            | TokenType::Var
+            | TokenType::While => Highlight::Keyword,
+            TokenType::EOF => return None,
+        };
+        Some(highlight)
+    }
+
+    /// The category of trivia, `None` for whitespace
+    pub fn of_trivia(kind: TriviaKind) -> Option<Highlight> {
+        match kind {
+            TriviaKind::LineComment | TriviaKind::BlockComment | TriviaKind::Shebang => {
+                Some(Highlight::Comment)
+            }
+            TriviaKind::Skipped => Some(Highlight::Error),
+            TriviaKind::Whitespace | TriviaKind::Newline => None,
+        }
+    }
+
+    /// The name used in HTML classes, e.g. `lox-keyword`
+    pub fn name(self) -> &'static str {
+        match self {
+            Highlight::Keyword => "keyword",
+            Highlight::Constant => "constant",
+            Highlight::Number => "number",
+            Highlight::String => "string",
+            Highlight::Operator => "operator",
+            Highlight::Punctuation => "punctuation",
+            Highlight::Identifier => "identifier",
+            Highlight::Comment => "comment",
+            Highlight::Error => "error",
+        }
+    }
+
+    /// The SGR parameters of the terminal colour, `None` to leave the text
+    /// uncoloured
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Highlight::Keyword => Some("35"),
+            Highlight::Constant | Highlight::Number => Some("33"),
+            Highlight::String => Some("32"),
+            Highlight::Operator => Some("36"),
+            Highlight::Punctuation => None,
+            Highlight::Identifier => Some("34"),
+            Highlight::Comment => Some("90"),
+            Highlight::Error => Some("4;31"),
+        }
+    }
+}
+
+/// Splits `source` into pieces that together give back the source, each with
+/// its category. Lexer errors are not printed, the caller decides how to
+/// report them; the text they skipped is highlighted as `Error`.
+pub fn highlight(source: &str) -> Vec<(Option<Highlight>, &str)> {
+    let mut pieces = vec![];
+    for token in Lexer::new(source).quiet().scan_lossless() {
+        push_token(&mut pieces, source, &token);
+    }
+    pieces
+}
+
+fn push_token<'a>(
+    pieces: &mut Vec<(Option<Highlight>, &'a str)>,
+    source: &'a str,
+    token: &LosslessToken<'a>,
+) {
+    for trivia in &token.leading {
+        pieces.push((Highlight::of_trivia(trivia.kind), trivia.text));
+    }
+
+    let lexeme = &source[token.token.span.start..token.token.span.end];
+
+    match token.token.token_type {
+        TokenType::String | TokenType::Interpolation => {
+            let (before, rest) = match lexeme.strip_prefix('}') {
+                Some(rest) => (Some(&lexeme[..1]), rest),
+                None => (None, lexeme),
+            };
+            let (string, after) = match rest.strip_suffix("${") {
+                Some(string) => (string, Some(&rest[string.len()..])),
+                None => (rest, None),
+            };
+            pieces.extend(before.map(|text| (Some(Highlight::Punctuation), text)));
+            pieces.push((Some(Highlight::String), string));
+            pieces.extend(after.map(|text| (Some(Highlight::Punctuation), text)));
+        }
+        TokenType::EOF => {}
+        token_type => pieces.push((Highlight::of_token(token_type), lexeme)),
+    }
+
+    for trivia in &token.trailing {
+        pieces.push((Highlight::of_trivia(trivia.kind), trivia.text));
+    }
+}
+
+/// The source with ANSI colour codes, for the terminal
+pub fn to_ansi(source: &str) -> String {
+    let mut output = String::new();
+    for (highlight, text) in highlight(source) {
+        match highlight.and_then(Highlight::ansi) {
+            Some(colour) => output.push_str(&format!("\x1b[{}m{}\x1b[0m", colour, text)),
+            None => output.push_str(text),
+        }
+    }
+    output
+}
+
+/// The source as an HTML `<pre>` block, with one `lox-<name>` class per
+/// category for the page's stylesheet to colour
+pub fn to_html(source: &str) -> String {
+    let mut output = String::from("<pre class=\"lox\"><code>");
+    for (highlight, text) in highlight(source) {
+        match highlight {
+            Some(highlight) => output.push_str(&format!(
+                "<span class=\"lox-{}\">{}</span>",
+                highlight.name(),
+                escape_html(text)
+            )),
+            None => output.push_str(&escape_html(text)),
+        }
+    }
+    output.push_str("</code></pre>\n");
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::{highlight, to_ansi, to_html, Highlight};
+
+    #[test]
+    fn test_categories() {
+        let source = "#!/usr/bin/env loxite\n-1 < x ? nil : \"a ${2}\" // note\n";
+        let pieces: Vec<(Option<Highlight>, &str)> = highlight(source)
+            .into_iter()
+            .filter(|(highlight, _)| highlight.is_some())
+            .collect();
+        assert_eq!(
+            pieces,
+            vec![
+                (Some(Highlight::Comment), "#!/usr/bin/env loxite"),
+                (Some(Highlight::Operator), "-"),
+                (Some(Highlight::Number), "1"),
+                (Some(Highlight::Operator), "<"),
+                (Some(Highlight::Identifier), "x"),
+                (Some(Highlight::Operator), "?"),
+                (Some(Highlight::Constant), "nil"),
+                (Some(Highlight::Operator), ":"),
+                (Some(Highlight::String), "\"a "),
+                (Some(Highlight::Punctuation), "${"),
+                (Some(Highlight::Number), "2"),
+                (Some(Highlight::Punctuation), "}"),
+                (Some(Highlight::String), "\""),
+                (Some(Highlight::Comment), "// note"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pieces_cover_the_source() {
+        for source in ["(1 + 2) /* c */ * 3\n", "1 @ é \"${\"${3}\"} x\"", "\"open"] {
+            let text: String = highlight(source)
+                .into_iter()
+                .map(|(_, text)| text)
+                .collect();
+            assert_eq!(text, source);
+        }
+    }
+
+    #[test]
+    fn test_ansi() {
+        assert_eq!(to_ansi("(true)\n"), "(\x1b[33mtrue\x1b[0m)\n");
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            to_html("1 < \"<b>\""),
+            "<pre class=\"lox\"><code><span class=\"lox-number\">1</span> \
+             <span class=\"lox-operator\">&lt;</span> \
+             <span class=\"lox-string\">&quot;&lt;b&gt;&quot;</span></code></pre>\n"
+        );
+    }
+}
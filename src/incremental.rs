@@ -0,0 +1,434 @@
+//! Incremental re-lexing for editors, which send small edits to a source they
+//! have already lexed. Only the tokens around an edit are lexed again; the
+//! rest are reused from the previous token stream and moved to their new
+//! position. `SyntaxNode::reparse` builds on this to re-parse only the part of
+//! the tree the edit touched.
+
+use std::{borrow::Cow, ops::Range};
+
+use crate::{
+    error::LexerError,
+    lexer::Lexer,
+    token::{LosslessToken, Span, Token, TokenType, Trivia},
+};
+
+/// Replaces the bytes in `range` of a source with `replacement`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    /// The source after the edit
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = source.to_string();
+        edited.replace_range(self.range.clone(), &self.replacement);
+        edited
+    }
+
+    /// How far the text after the edit moves
+    fn delta(&self) -> isize {
+        self.replacement.len() as isize - self.range.len() as isize
+    }
+}
+
+/// The lossless tokens of an edited source. The old tokens
+/// `start..old_end` were replaced by the re-lexed tokens `start..new_end`,
+/// and the others were reused.
+#[derive(Debug)]
+pub struct Relexed<'a> {
+    pub tokens: Vec<LosslessToken<'a>>,
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+    /// The lexer errors from the end of token `start - 1` (or the start of
+    /// the source) to the end of token `new_end - 1`. Errors outside that
+    /// range are the same as before the edit, moved with their text.
+    pub errors: Vec<LexerError>,
+}
+
+/// Lexes `new_source`, the result of applying `edit` to a source whose
+/// lossless tokens were `old`, giving the same tokens as lexing it from
+/// scratch.
+///
+/// Lexing restarts after the last token that cannot have seen the edit (the
+/// lexer looks at most two characters past a token, and those come before
+/// the end of the following token) and that is not inside an interpolated
+/// string. The trailing trivia of that token is lexed again. Lexing stops
+/// once it reaches a token after the edit that started an old token in the
+/// same state, as everything from there on lexes the same. Errors are not
+/// printed but returned in `errors`.
+pub fn relex<'a>(old: &[LosslessToken<'_>], edit: &TextEdit, new_source: &'a str) -> Relexed<'a> {
+    let mut start = 0;
+    let mut open = 0;
+    for (index, pair) in old.windows(2).enumerate() {
+        open += interpolation_change(&pair[0].token);
+        if pair[1].token.span.end >= edit.range.start {
+            break;
+        }
+        if open == 0 {
+            start = index + 1;
+        }
+    }
+
+    let mut tokens: Vec<LosslessToken<'a>> = old[..start]
+        .iter()
+        .map(|token| moved(token, new_source, 0, 0))
+        .collect();
+    // The trailing trivia of the last reused token is lexed again, as text
+    // after it may join or extend it
+    let (mut lexer, offset) = match tokens.last_mut() {
+        Some(previous) => {
+            previous.trailing.clear();
+            let offset = previous.token.span.end;
+            (
+                Lexer::resume(new_source, offset, previous.token.line).quiet(),
+                offset,
+            )
+        }
+        None => (Lexer::new(new_source).quiet(), 0),
+    };
+
+    let edit_end = edit.range.start + edit.replacement.len();
+    let delta = edit.delta();
+    let mut scanned = vec![];
+    let mut new_open = 0;
+    let mut old_index = start;
+    let mut old_open = 0;
+    let mut resync = None;
+
+    for token in lexer.by_ref() {
+        if token.span.start >= edit_end && new_open == 0 {
+            let old_start = token.span.start as isize - delta;
+            while old_index < old.len() && (old[old_index].token.span.start as isize) < old_start {
+                old_open += interpolation_change(&old[old_index].token);
+                old_index += 1;
+            }
+            let matches = old.get(old_index).is_some_and(|candidate| {
+                candidate.token.span.start as isize == old_start
+                    && candidate.token.token_type == token.token_type
+            });
+            if matches && old_open == 0 {
+                scanned.push(token);
+                resync = Some(old_index);
+                break;
+            }
+        }
+
+        new_open += interpolation_change(&token);
+        scanned.push(token);
+    }
+
+    lexer.attach_trivia(offset, scanned, &mut tokens);
+    let errors = lexer.errors().to_vec();
+    let old_end = match resync {
+        Some(old_index) => {
+            // The token starting the reused part gets the trailing trivia
+            // that was after it, which the lexer has not reached
+            let last = tokens.last_mut().expect("resynced on a scanned token");
+            let line_delta = last.token.line as isize - old[old_index].token.line as isize;
+            last.trailing = old[old_index]
+                .trailing
+                .iter()
+                .map(|trivia| moved_trivia(trivia, new_source, delta))
+                .collect();
+
+            let new_end = tokens.len();
+            tokens.extend(
+                old[old_index + 1..]
+                    .iter()
+                    .map(|token| moved(token, new_source, delta, line_delta)),
+            );
+            return Relexed {
+                tokens,
+                start,
+                old_end: old_index + 1,
+                new_end,
+                errors,
+            };
+        }
+        None => old.len(),
+    };
+
+    Relexed {
+        new_end: tokens.len(),
+        tokens,
+        start,
+        old_end,
+        errors,
+    }
+}
+
+/// How a token changes the number of interpolated strings open around it:
+/// `"a ${` opens one and `}"` closes it, while `} b ${` keeps it open
+fn interpolation_change(token: &Token) -> isize {
+    let continues = token.lexeme.starts_with('}');
+    match token.token_type {
+        TokenType::Interpolation if !continues => 1,
+        TokenType::String if continues => -1,
+        _ => 0,
+    }
+}
+
+/// `token` moved by `delta` bytes and `line_delta` lines, with its text now
+/// borrowed from `source`
+fn moved<'a>(
+    token: &LosslessToken<'_>,
+    source: &'a str,
+    delta: isize,
+    line_delta: isize,
+) -> LosslessToken<'a> {
+    let span = shifted(token.token.span, delta);
+    let lexeme = match &token.token.lexeme {
+        Cow::Borrowed(_) => Cow::Borrowed(&source[span.start..span.end]),
+        Cow::Owned(lexeme) => Cow::Owned(lexeme.clone()),
+    };
+
+    LosslessToken {
+        leading: token
+            .leading
+            .iter()
+            .map(|trivia| moved_trivia(trivia, source, delta))
+            .collect(),
+        token: Token {
+            token_type: token.token.token_type,
+            lexeme,
+            literal: token.token.literal.clone(),
+            line: (token.token.line as isize + line_delta) as usize,
+            span,
+        },
+        trailing: token
+            .trailing
+            .iter()
+            .map(|trivia| moved_trivia(trivia, source, delta))
+            .collect(),
+    }
+}
+
+fn moved_trivia<'a>(trivia: &Trivia<'_>, source: &'a str, delta: isize) -> Trivia<'a> {
+    let span = shifted(trivia.span, delta);
+    Trivia {
+        kind: trivia.kind,
+        text: &source[span.start..span.end],
+        span,
+    }
+}
+
+fn shifted(span: Span, delta: isize) -> Span {
+    Span {
+        start: (span.start as isize + delta) as usize,
+        end: (span.end as isize + delta) as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::{
+        cst::SyntaxNode,
+        error::LexerError,
+        incremental::{relex, Relexed, TextEdit},
+        lexer::Lexer,
+        token::LosslessToken,
+    };
+
+    /// Pieces of the random sources and edits, including ones the lexer
+    /// rejects or that change how their neighbours lex
+    const PIECES: &[&str] = &[
+        "1", "2.", ".5", "1_", "_", "0x", "0b2", "e5", "1e", "+", "-", "*", "/", "?", ":", "(",
+        ")", "==", "!", "<", ">=", ",", "nil", "true", "x", "é", "\"", "\"s ${", "}", "\\", "//",
+        "/*", "*/", "#", "#!", "@", " ", "  ", "\n", "\t", "\r\n",
+    ];
+
+    /// The seeds of the random cases. `LOX_FUZZ_SEEDS`, a comma-separated
+    /// list, replaces them to try other cases, e.g. in a loop over
+    /// `$RANDOM`.
+    fn seeds() -> Vec<u64> {
+        match env::var("LOX_FUZZ_SEEDS") {
+            Ok(seeds) => seeds
+                .split(',')
+                .map(|seed| seed.trim().parse().expect("LOX_FUZZ_SEEDS holds numbers"))
+                .collect(),
+            Err(_) => vec![0x2545_f491_4f6c_dd1d, 1, 42, 0xdead_beef],
+        }
+    }
+
+    /// A xorshift generator, whose seed is printed on failure to reproduce
+    /// the case
+    struct Random(u64);
+
+    impl Random {
+        fn new(seed: u64) -> Random {
+            // Xorshift stays at 0 forever
+            Random(seed.max(1))
+        }
+
+        fn below(&mut self, limit: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize % limit
+        }
+
+        fn text(&mut self, pieces: usize) -> String {
+            (0..self.below(pieces + 1))
+                .map(|_| PIECES[self.below(PIECES.len())])
+                .collect()
+        }
+
+        /// An edit of up to 4 characters at a character boundary of `source`
+        fn edit(&mut self, source: &str) -> TextEdit {
+            let boundaries: Vec<usize> = source
+                .char_indices()
+                .map(|(offset, _)| offset)
+                .chain([source.len()])
+                .collect();
+            let start = self.below(boundaries.len());
+            let end = (start + self.below(5)).min(boundaries.len() - 1);
+            TextEdit {
+                range: boundaries[start]..boundaries[end],
+                replacement: self.text(3),
+            }
+        }
+    }
+
+    #[test]
+    fn test_relex_matches_lexing_from_scratch() {
+        let cases = [("-2.#!", 3..3, "1_"), ("-: ${1 2./*1x}", 9..11, "")];
+        for (source, range, replacement) in cases {
+            let old = scan(source).0;
+            let edit = TextEdit {
+                range,
+                replacement: replacement.to_string(),
+            };
+            let new_source = edit.apply(source);
+            let relexed = relex(&old, &edit, &new_source);
+            assert_eq!(relexed.tokens, scan(&new_source).0);
+        }
+
+        for seed in seeds() {
+            let mut random = Random::new(seed);
+            for _ in 0..500 {
+                let source = random.text(30);
+                let old = scan(&source).0;
+                for _ in 0..20 {
+                    let edit = random.edit(&source);
+                    let new_source = edit.apply(&source);
+                    let relexed = relex(&old, &edit, &new_source);
+                    let (tokens, errors) = scan(&new_source);
+                    let context = format!("{:?} with {:?} (seed {})", source, edit, seed);
+                    assert_eq!(
+                        relexed_errors(&new_source, &relexed, &errors),
+                        relexed.errors,
+                        "{}",
+                        context
+                    );
+                    assert_eq!(relexed.tokens, tokens, "{}", context);
+                }
+            }
+        }
+    }
+
+    /// Lexes `source` from scratch
+    fn scan(source: &str) -> (Vec<LosslessToken<'_>>, Vec<LexerError>) {
+        let mut lexer = Lexer::new(source).quiet();
+        let tokens = lexer.scan_lossless();
+        (tokens, lexer.errors().to_vec())
+    }
+
+    /// The errors among `errors` in the part of `source` that `relexed` lexed
+    /// again
+    fn relexed_errors(source: &str, relexed: &Relexed, errors: &[LexerError]) -> Vec<LexerError> {
+        let start = match relexed.start {
+            0 => 0,
+            start => relexed.tokens[start - 1].token.span.end,
+        };
+        let end = relexed.tokens[relexed.new_end - 1].token.span.end;
+        errors
+            .iter()
+            .filter(|error| {
+                let line_start: usize = source
+                    .split_inclusive('\n')
+                    .take(error.line - 1)
+                    .map(str::len)
+                    .sum();
+                let offset = source[line_start..]
+                    .char_indices()
+                    .nth(error.column - 1)
+                    .map_or(source.len(), |(offset, _)| line_start + offset);
+                (start..end).contains(&offset)
+            })
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_relex_reuses_tokens_away_from_edit() {
+        let source = (0..1000)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let old = Lexer::new(&source).scan_lossless();
+        let offset = source.find("500").unwrap();
+        let edit = TextEdit {
+            range: offset..offset + 3,
+            replacement: "(5 * 6)".to_string(),
+        };
+        let new_source = edit.apply(&source);
+
+        let relexed = relex(&old, &edit, &new_source);
+        assert!(relexed.new_end - relexed.start < 10);
+        assert_eq!(relexed.tokens.len(), old.len() + 4);
+        assert_eq!(relexed.tokens, Lexer::new(&new_source).scan_lossless());
+    }
+
+    #[test]
+    fn test_reparse_matches_parsing_from_scratch() {
+        for seed in seeds() {
+            let mut random = Random::new(seed);
+            for _ in 0..125 {
+                let source = random.text(30);
+                let tree = SyntaxNode::parse(&source);
+                for _ in 0..20 {
+                    let edit = random.edit(&source);
+                    let new_source = edit.apply(&source);
+                    assert_eq!(
+                        tree.reparse(&edit, &new_source),
+                        SyntaxNode::parse(&new_source),
+                        "{:?} with {:?} (seed {})",
+                        source,
+                        edit,
+                        seed
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_successive_edits() {
+        let mut source = String::from("(1 + 2) * (3 + 4)");
+        let mut tree = SyntaxNode::parse(&source).to_string();
+        let edits = [
+            (1..2, "10"),
+            (12..13, "\"${5}\""),
+            (0..0, "-"),
+            (24..24, " // done"),
+        ];
+        for (range, replacement) in edits {
+            let edit = TextEdit {
+                range,
+                replacement: replacement.to_string(),
+            };
+            let new_source = edit.apply(&source);
+            let old = SyntaxNode::parse(&source);
+            let new = old.reparse(&edit, &new_source);
+            assert_eq!(new, SyntaxNode::parse(&new_source));
+            tree = new.to_string();
+            source = new_source;
+        }
+        assert_eq!(tree, "-(10 + 2) * (\"${5}\" + 4) // done");
+    }
+}
@@ -2,10 +2,14 @@ use std::fmt::{self};
 
 use crate::{
     error::{LoxiteError, RuntimeError},
-    expr::{BinaryExpr, Expr},
+    expr::{
+        BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+    },
     token::{TokenLiteral, TokenType},
+    visitor::{VisitResult, Visitor},
 };
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(String),
     Number(f64),
@@ -24,38 +28,59 @@ impl fmt::Display for Value {
     }
 }
 
-pub struct Interpreter {}
+impl VisitResult for Value {
+    fn output() -> Value {
+        Value::Nil
+    }
+}
+
+/// Observes evaluation, e.g. to stop at breakpoints. Only unary and binary
+/// operations are reported, as they are the expressions with a token, and so
+/// a line.
+pub trait EvalHook {
+    /// Called before the operands of `expr` are evaluated
+    fn before(&mut self, expr: &Expr);
+    /// Called with the value of `expr`, unless evaluating it failed
+    fn after(&mut self, expr: &Expr, value: &Value);
+}
+
+#[derive(Default)]
+pub struct Interpreter {
+    hook: Option<Box<dyn EvalHook>>,
+}
 
 impl Interpreter {
-    pub fn interpreter(&self, expr: &Expr) {
-        let evaluation = self.evaluate(expr);
-        match evaluation {
-            Ok(value) => println!("{}", value),
-            Err(err) => println!("{}", err),
-        }
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn evaluate(&self, expr: &Expr) -> Result<Value, LoxiteError> {
-        match expr {
-            Expr::Literal(literal_expr) => match &literal_expr.value {
-                TokenLiteral::Number(number) => Ok(Value::Number(*number)),
-                TokenLiteral::String(string) => Ok(Value::String(string.clone())),
-                TokenLiteral::Boolean(boolean) => Ok(Value::Boolean(*boolean)),
-                TokenLiteral::Empty => Ok(Value::Nil),
-            },
-            Expr::Grouping(grouping_expr) => self.evaluate(&grouping_expr.expression),
-            Expr::Unary(unary_expr) => {
-                let right = self.evaluate(&unary_expr.right)?;
-                match right {
-                    Value::Number(value) => Ok(Value::Number(-value)),
-                    value => Ok(Value::Boolean(!self.is_truthy(&value))),
-                }
-            }
-            Expr::Binary(binary_expr) => self.evaluate_binary(binary_expr),
+    /// Reports every operation evaluated from now on to `hook`
+    pub fn set_hook(&mut self, hook: Box<dyn EvalHook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Evaluates `expr` and prints its value. Runtime errors are returned to
+    /// the caller, which decides how to report them.
+    pub fn interpreter(&mut self, expr: &Expr) -> Result<(), LoxiteError> {
+        let value = self.evaluate(expr)?;
+        println!("{}", value);
+        Ok(())
+    }
+
+    /// Evaluates `expr` without printing it
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, LoxiteError> {
+        let operation = matches!(expr, Expr::Unary(_) | Expr::Binary(_));
+        if let (true, Some(hook)) = (operation, &mut self.hook) {
+            hook.before(expr);
         }
+        let value = self.visit_expr(expr)?;
+        if let (true, Some(hook)) = (operation, &mut self.hook) {
+            hook.after(expr, &value);
+        }
+        Ok(value)
     }
 
-    fn evaluate_binary(&self, binary_expr: &BinaryExpr) -> Result<Value, LoxiteError> {
+    fn evaluate_binary(&mut self, binary_expr: &BinaryExpr) -> Result<Value, LoxiteError> {
         let left = self.evaluate(&binary_expr.left)?;
         let right = self.evaluate(&binary_expr.right)?;
         let token = &binary_expr.operator;
@@ -98,9 +123,10 @@ impl Interpreter {
             },
             TokenType::BangEqual => Ok(Value::Boolean(!self.is_equal(&left, &right))),
             TokenType::EqualEqual => Ok(Value::Boolean(self.is_equal(&left, &right))),
+            TokenType::Comma => Ok(right),
             _ => Err(LoxiteError::Runtime(RuntimeError::error(
                 token,
-                "I don't thing this will ever be called",
+                "Unknown binary operator.",
             ))),
         }
     }
@@ -115,11 +141,181 @@ impl Interpreter {
 
     fn is_equal(&self, value_1: &Value, value_2: &Value) -> bool {
         match (value_1, value_2) {
-            (Value::Number(num_1), Value::Number(num_2)) => return num_1 == num_2,
-            (Value::String(str_1), Value::String(str_2)) => return str_1 == str_2,
-            (Value::Boolean(bool_1), Value::Boolean(bool_2)) => return bool_1 == bool_2,
-            (Value::Nil, Value::Nil) => return true,
-            _ => return false,
+            (Value::Number(num_1), Value::Number(num_2)) => num_1 == num_2,
+            (Value::String(str_1), Value::String(str_2)) => str_1 == str_2,
+            (Value::Boolean(bool_1), Value::Boolean(bool_2)) => bool_1 == bool_2,
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Visitor<Result<Value, LoxiteError>> for Interpreter {
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> Result<Value, LoxiteError> {
+        let right = self.evaluate(&expr.right)?;
+        let token = &expr.operator;
+
+        match token.token_type {
+            TokenType::Minus => match right {
+                Value::Number(value) => Ok(Value::Number(-value)),
+                _ => Err(LoxiteError::Runtime(RuntimeError::number_operand(token))),
+            },
+            TokenType::Bang => Ok(Value::Boolean(!self.is_truthy(&right))),
+            _ => Err(LoxiteError::Runtime(RuntimeError::error(
+                token,
+                "Unknown unary operator.",
+            ))),
+        }
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> Result<Value, LoxiteError> {
+        self.evaluate_binary(expr)
+    }
+
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> Result<Value, LoxiteError> {
+        self.evaluate(&expr.expression)
+    }
+
+    /// Only the selected branch is evaluated
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> Result<Value, LoxiteError> {
+        let condition = self.evaluate(&expr.condition)?;
+        if self.is_truthy(&condition) {
+            self.evaluate(&expr.then_branch)
+        } else {
+            self.evaluate(&expr.else_branch)
+        }
+    }
+
+    /// Embedded values are converted with `Display`, the same way `print`
+    /// shows them
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> Result<Value, LoxiteError> {
+        let mut result = expr.strings[0].clone();
+        for (expression, string) in expr.expressions.iter().zip(&expr.strings[1..]) {
+            result.push_str(&self.evaluate(expression)?.to_string());
+            result.push_str(string);
         }
+        Ok(Value::String(result))
+    }
+
+    fn visit_literal(&mut self, expr: &LiteralExpr) -> Result<Value, LoxiteError> {
+        match &expr.value {
+            TokenLiteral::Number(number) => Ok(Value::Number(*number)),
+            TokenLiteral::String(string) => Ok(Value::String(string.clone())),
+            TokenLiteral::Boolean(boolean) => Ok(Value::Boolean(*boolean)),
+            TokenLiteral::Empty => Ok(Value::Nil),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        expr::Expr,
+        formatter::Formatter,
+        interpreter::{EvalHook, Interpreter, Value},
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    fn evaluate(source: &str) -> Result<Value, String> {
+        let tokens = Lexer::new(source).scan_tokens();
+        let expr = Parser::new(tokens).parser().unwrap();
+        Interpreter::new()
+            .evaluate(&expr)
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        let number_error = Err("Runtime Error: [line 1]: Operand must be a number.".to_string());
+        let cases = [
+            ("-3", Ok(Value::Number(-3.0))),
+            ("-\"a\"", number_error.clone()),
+            ("-true", number_error.clone()),
+            ("-false", number_error.clone()),
+            ("-nil", number_error.clone()),
+            ("--3", Ok(Value::Number(3.0))),
+            ("!3", Ok(Value::Boolean(false))),
+            ("!0", Ok(Value::Boolean(false))),
+            ("!\"a\"", Ok(Value::Boolean(false))),
+            ("!\"\"", Ok(Value::Boolean(false))),
+            ("!true", Ok(Value::Boolean(false))),
+            ("!false", Ok(Value::Boolean(true))),
+            ("!nil", Ok(Value::Boolean(true))),
+            ("!!nil", Ok(Value::Boolean(false))),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(evaluate(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_conditional_evaluates_only_selected_branch() {
+        assert_eq!(evaluate("true ? 1 : -\"a\""), Ok(Value::Number(1.0)));
+        assert_eq!(evaluate("nil ? -\"a\" : 2"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            evaluate("0 ? \"zero\" : \"other\""),
+            Ok(Value::String("zero".to_string()))
+        );
+        assert_eq!(
+            evaluate("false ? 1 : false ? 2 : 3"),
+            Ok(Value::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq!(
+            evaluate("\"${1} + ${\"two\"} is ${1 + 2}, ${nil}, ${!nil}\""),
+            Ok(Value::String("1 + two is 3, nil, true".to_string()))
+        );
+        assert_eq!(
+            evaluate("\"a ${\"b ${1 < 2 ? \"c\" : \"d\"}\"}\""),
+            Ok(Value::String("a b c".to_string()))
+        );
+        assert_eq!(
+            evaluate("\"${-\"a\"}\""),
+            Err("Runtime Error: [line 1]: Operand must be a number.".to_string())
+        );
+    }
+
+    /// Records the operations it sees
+    struct Trace(Rc<RefCell<Vec<String>>>);
+
+    impl EvalHook for Trace {
+        fn before(&mut self, expr: &Expr) {
+            let code = Formatter.format_expr(expr);
+            self.0.borrow_mut().push(format!("before {}", code));
+        }
+
+        fn after(&mut self, expr: &Expr, value: &Value) {
+            let code = Formatter.format_expr(expr);
+            self.0.borrow_mut().push(format!("{} = {}", code, value));
+        }
+    }
+
+    #[test]
+    fn test_hook_sees_operations() {
+        let trace = Rc::new(RefCell::new(vec![]));
+        let tokens = Lexer::new("-(1 + 2) * (true ? 3 : 4 / 0)").scan_tokens();
+        let expr = Parser::new(tokens).parser().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(Box::new(Trace(trace.clone())));
+        interpreter.evaluate(&expr).unwrap();
+
+        assert_eq!(
+            *trace.borrow(),
+            vec![
+                "before -(1 + 2) * (true ? 3 : 4 / 0)",
+                "before -(1 + 2)",
+                "before 1 + 2",
+                "1 + 2 = 3",
+                "-(1 + 2) = -3",
+                "-(1 + 2) * (true ? 3 : 4 / 0) = -9",
+            ]
+        );
     }
 }
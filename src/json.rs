@@ -0,0 +1,518 @@
+use std::{
+    error::Error,
+    fmt::{self, Write},
+};
+
+/// A JSON document. Object members keep their insertion order so output is
+/// stable and readable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// Types with a JSON representation
+pub trait ToJson {
+    fn to_json(&self) -> JsonValue;
+}
+
+/// Types that can be rebuilt from the JSON written by their `ToJson`
+pub trait FromJson: Sized {
+    fn from_json(json: &JsonValue) -> Result<Self, JsonError>;
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    pub message: String,
+}
+
+impl JsonError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "JSON error: {}", self.message)
+    }
+}
+
+impl Error for JsonError {}
+
+impl JsonValue {
+    pub fn parse(source: &str) -> Result<JsonValue, JsonError> {
+        let mut reader = JsonReader {
+            source: source.as_bytes(),
+            current: 0,
+            depth: 0,
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.current != reader.source.len() {
+            return Err(reader.error("Unexpected trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Builds an object from `(key, value)` pairs
+    pub fn object(members: Vec<(&str, JsonValue)>) -> JsonValue {
+        JsonValue::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Looks up `key` when this is an object
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Indented output with two spaces per level, for files read by people
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0);
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, depth: usize) {
+        let indent = |output: &mut String, depth: usize| {
+            output.push('\n');
+            output.push_str(&"  ".repeat(depth));
+        };
+
+        match self {
+            JsonValue::Array(values) if !values.is_empty() => {
+                output.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+                    indent(output, depth + 1);
+                    value.write_pretty(output, depth + 1);
+                }
+                indent(output, depth);
+                output.push(']');
+            }
+            JsonValue::Object(members) if !members.is_empty() => {
+                output.push('{');
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+                    indent(output, depth + 1);
+                    write_string(output, key);
+                    output.push_str(": ");
+                    value.write_pretty(output, depth + 1);
+                }
+                indent(output, depth);
+                output.push('}');
+            }
+            value => output.push_str(&value.to_string()),
+        }
+    }
+}
+
+/// Compact output on a single line. JSON cannot represent infinities and
+/// NaN, so they are written as `null`; formats that need them, like the AST
+/// schema, must encode them before.
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Boolean(value) => write!(f, "{}", value),
+            JsonValue::Number(value) if value.is_finite() => write!(f, "{}", value),
+            JsonValue::Number(_) => write!(f, "null"),
+            JsonValue::String(value) => {
+                let mut output = String::new();
+                write_string(&mut output, value);
+                write!(f, "{}", output)
+            }
+            JsonValue::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", JsonValue::String(key.clone()), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", ch as u32);
+            }
+            ch => output.push(ch),
+        }
+    }
+    output.push('"');
+}
+
+/// How deeply arrays and objects may nest, so hostile input cannot overflow
+/// the stack of the recursive reader or of the code walking its result
+const MAX_DEPTH: usize = 512;
+
+struct JsonReader<'a> {
+    source: &'a [u8],
+    current: usize,
+    /// Arrays and objects open around the current position
+    depth: usize,
+}
+
+impl JsonReader<'_> {
+    fn value(&mut self) -> Result<JsonValue, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", JsonValue::Null),
+            Some(b't') => self.keyword("true", JsonValue::Boolean(true)),
+            Some(b'f') => self.keyword("false", JsonValue::Boolean(false)),
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(open @ (b'[' | b'{')) => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("Too deeply nested"));
+                }
+                self.depth += 1;
+                let value = if open == b'[' {
+                    self.array()
+                } else {
+                    self.object()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        if self.source[self.current..].starts_with(keyword.as_bytes()) {
+            self.current += keyword.len();
+            Ok(value)
+        } else {
+            Err(self.error("Unexpected character"))
+        }
+    }
+
+    /// A number as RFC 8259 writes them: no leading zeros, `+` sign, or
+    /// missing digits around the `.` or in the exponent
+    fn number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.current;
+        if self.peek() == Some(b'-') {
+            self.current += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.current += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("Invalid number")),
+        }
+        if self.peek() == Some(b'.') {
+            self.current += 1;
+            if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                return Err(self.error("Expected digits after '.'"));
+            }
+            self.digits();
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.current += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.current += 1;
+            }
+            if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                return Err(self.error("Expected digits in exponent"));
+            }
+            self.digits();
+        }
+
+        std::str::from_utf8(&self.source[start..self.current])
+            .ok()
+            .and_then(|text| text.parse::<f64>().ok())
+            .map(JsonValue::Number)
+            .ok_or_else(|| self.error("Invalid number"))
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.current += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+
+        loop {
+            match self.advance() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let ch = match self.advance() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(ch.encode_utf8(&mut buffer).as_bytes());
+                }
+                Some(byte) => bytes.push(byte),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error("Invalid UTF-8 in string"))
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.advance() != Some(b'\\') || self.advance() != Some(b'u') {
+                return Err(self.error("Unpaired surrogate"));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                return Err(self.error("Unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    /// Exactly four hex digits; `from_str_radix` alone would accept a sign
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .source
+            .get(self.current..self.current + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .ok_or_else(|| self.error("Invalid unicode escape"))?;
+        self.current += 4;
+        Ok(digits.iter().fold(0, |code, digit| {
+            code * 16 + (*digit as char).to_digit(16).unwrap_or_default()
+        }))
+    }
+
+    fn array(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.current += 1;
+            return Ok(JsonValue::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.advance() {
+                Some(b',') => continue,
+                Some(b']') => return Ok(JsonValue::Array(values)),
+                _ => return Err(self.error("Expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect(b'{')?;
+        let mut members = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.current += 1;
+            return Ok(JsonValue::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.advance() {
+                Some(b',') => continue,
+                Some(b'}') => return Ok(JsonValue::Object(members)),
+                _ => return Err(self.error("Expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), JsonError> {
+        match self.advance() {
+            Some(byte) if byte == expected => Ok(()),
+            _ => Err(self.error(&format!("Expected '{}'", expected as char))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.current += 1;
+        Some(byte)
+    }
+
+    fn error(&self, message: &str) -> JsonError {
+        JsonError::new(&format!("{} at offset {}", message, self.current))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{JsonValue, MAX_DEPTH};
+
+    #[test]
+    fn test_parse_and_print() {
+        let source = r#"{"a": [1, -2.5e3, true, null], "b": "x\"\né😀"}"#;
+        let value = JsonValue::parse(source).unwrap();
+
+        assert_eq!(value.get("a").unwrap().as_array().unwrap().len(), 4);
+        assert_eq!(value.get("b").unwrap().as_str(), Some("x\"\né😀"));
+        assert_eq!(
+            value.to_string(),
+            r#"{"a":[1,-2500,true,null],"b":"x\"\né😀"}"#
+        );
+        assert_eq!(JsonValue::parse(&value.to_pretty_string()).unwrap(), value);
+        assert_eq!(
+            JsonValue::parse(r#""\ud83d\ude00 \u00e9""#).unwrap(),
+            JsonValue::String("😀 é".to_string())
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        for (source, value) in [
+            ("0", 0.0),
+            ("-0.5", -0.5),
+            ("10", 10.0),
+            ("1E3", 1000.0),
+            ("2.5e-1", 0.25),
+            ("1e+2", 100.0),
+        ] {
+            assert_eq!(JsonValue::parse(source).unwrap(), JsonValue::Number(value));
+        }
+    }
+
+    #[test]
+    fn test_nesting_is_capped() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(JsonValue::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(JsonValue::parse(&nested(MAX_DEPTH + 1)).is_err());
+
+        let objects = "{\"a\":".repeat(100_000) + &"}".repeat(100_000);
+        let error = JsonValue::parse(&objects).unwrap_err();
+        assert!(error.message.starts_with("Too deeply nested"));
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        for source in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"abc",
+            "tru",
+            "1 2",
+            r#""\u+041""#,
+            r#""\ud83d\u0041""#,
+            r#""\ud83d""#,
+            "01",
+            "-01",
+            "1.",
+            ".5",
+            "-",
+            "+1",
+            "1e",
+            "1e+",
+            "1.e5",
+            "--1",
+        ] {
+            assert!(
+                JsonValue::parse(source).is_err(),
+                "{:?} should fail",
+                source
+            );
+        }
+    }
+}
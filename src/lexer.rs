@@ -1,65 +1,249 @@
-use std::collections::HashMap;
-use std::mem;
+use std::borrow::Cow;
 
 use crate::error::LexerError;
 use crate::error::LoxiteError;
+use crate::token::Comment;
+use crate::token::LosslessToken;
+use crate::token::Span;
 use crate::token::Token;
 use crate::token::TokenLiteral;
 use crate::token::TokenType;
-
+use crate::token::Trivia;
+use crate::token::TriviaKind;
+
+/// Scans Lox source into tokens whose lexemes borrow from it.
+///
+/// The lexer is an iterator that scans one token per call to `next`, ending
+/// with `EOF`, so the parser can pull tokens as it needs them; `scan_tokens`
+/// collects them all. Positions are byte offsets into the source.
 #[derive(Debug)]
 pub struct Lexer<'a> {
     /// If an error happens during lexing
     pub had_error: bool,
-    source: Vec<char>,
-    tokens: Vec<Token>,
+    errors: Vec<LexerError>,
+    /// Set to collect errors without printing them
+    quiet: bool,
+    source: &'a str,
+    /// The token scanned by the last call to `tokenize`, if it produced one
+    next_token: Option<Token<'a>>,
+    /// Set once the `EOF` token has been returned
+    finished: bool,
+    comments: Vec<Comment>,
     start: usize,
     current: usize,
     line: usize,
-    keywords: HashMap<&'a str, TokenType>,
+    /// Offset where the current line starts, to work out columns
+    line_start: usize,
+    /// One entry per `${` still open, counting the `{` opened inside it so
+    /// the lexer knows which `}` goes back to the string
+    interpolations: Vec<usize>,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(source: &String) -> Self {
-        let keywords = Lexer::get_keywords();
+    pub fn new(source: &'a str) -> Self {
         Lexer {
-            source: source.chars().collect(),
-            tokens: vec![],
+            source,
+            next_token: None,
+            finished: false,
+            comments: vec![],
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            interpolations: vec![],
             had_error: false,
-            keywords,
+            errors: vec![],
+            quiet: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.tokenize();
+    /// Collects errors in `errors` without printing them, for tools that
+    /// report them their own way
+    pub fn quiet(self) -> Self {
+        Lexer {
+            quiet: true,
+            ..self
         }
-        self.tokens.push(Token {
-            token_type: TokenType::EOF,
-            lexeme: "".to_string(),
-            literal: TokenLiteral::Empty,
-            line: self.line,
-        });
+    }
 
-        mem::take(&mut self.tokens)
+    /// Scans all the remaining tokens
+    pub fn scan_tokens(&mut self) -> Vec<Token<'a>> {
+        self.by_ref().collect()
+    }
+
+    /// Starts scanning `source` at byte `offset`, on `line`, as if everything
+    /// before had been scanned already and left no string open. Used to
+    /// re-lex the part of a source around an edit.
+    pub(crate) fn resume(source: &'a str, offset: usize, line: usize) -> Self {
+        Lexer {
+            start: offset,
+            current: offset,
+            line,
+            line_start: source[..offset]
+                .rfind('\n')
+                .map_or(0, |newline| newline + 1),
+            ..Lexer::new(source)
+        }
+    }
+
+    /// Scans the whole source in lossless mode: every token comes with the
+    /// whitespace, comments and skipped text around it as trivia. Call it on
+    /// a fresh lexer, since trivia before tokens already scanned is lost.
+    pub fn scan_lossless(&mut self) -> Vec<LosslessToken<'a>> {
+        let start = self.current;
+        let tokens = self.scan_tokens();
+        let mut lossless = Vec::with_capacity(tokens.len());
+        self.attach_trivia(start, tokens, &mut lossless);
+        lossless
+    }
+
+    /// Adds the trivia between `tokens`, which were scanned by this lexer
+    /// from offset `start` on, and appends them to `lossless`. The trivia
+    /// before the first newline at `start` extends the trailing trivia of the
+    /// last token already in `lossless`. The last token gets no trailing
+    /// trivia.
+    pub(crate) fn attach_trivia(
+        &self,
+        start: usize,
+        tokens: Vec<Token<'a>>,
+        lossless: &mut Vec<LosslessToken<'a>>,
+    ) {
+        let mut comments = self.comments.iter().peekable();
+        let mut gap_start = start;
+
+        for token in tokens {
+            let mut leading = vec![];
+            let mut trailing_ends = false;
+            for trivia in self.trivia(gap_start, token.span.start, &mut comments) {
+                match lossless.last_mut() {
+                    Some(previous) if !trailing_ends && trivia.kind != TriviaKind::Newline => {
+                        previous.trailing.push(trivia)
+                    }
+                    _ => {
+                        trailing_ends = true;
+                        leading.push(trivia);
+                    }
+                }
+            }
+
+            gap_start = token.span.end;
+            lossless.push(LosslessToken {
+                leading,
+                token,
+                trailing: vec![],
+            });
+        }
+    }
+
+    /// Splits the text between two tokens into trivia. `comments` is
+    /// advanced past the comments found in it.
+    fn trivia<'c>(
+        &self,
+        start: usize,
+        end: usize,
+        comments: &mut std::iter::Peekable<impl Iterator<Item = &'c Comment>>,
+    ) -> Vec<Trivia<'a>> {
+        let mut trivia = vec![];
+        let mut position = start;
+
+        while position < end {
+            let rest = &self.source[position..end];
+            let (kind, length) = match comments.next_if(|comment| comment.span.start == position) {
+                Some(comment) if rest.starts_with("//") => {
+                    (TriviaKind::LineComment, comment.span.end - position)
+                }
+                Some(comment) => (TriviaKind::BlockComment, comment.span.end - position),
+                None if position == 0 && rest.starts_with("#!") => {
+                    (TriviaKind::Shebang, rest.find('\n').unwrap_or(rest.len()))
+                }
+                None if rest.starts_with('\n') => (TriviaKind::Newline, 1),
+                None => {
+                    let is_space = |ch: char| matches!(ch, ' ' | '\t' | '\r');
+                    let kind = if rest.starts_with(is_space) {
+                        TriviaKind::Whitespace
+                    } else {
+                        TriviaKind::Skipped
+                    };
+                    let length = rest
+                        .char_indices()
+                        .skip(1)
+                        .find(|&(offset, ch)| {
+                            let at_comment = comments
+                                .peek()
+                                .is_some_and(|comment| comment.span.start == position + offset);
+                            at_comment
+                                || ch == '\n'
+                                || is_space(ch) != (kind == TriviaKind::Whitespace)
+                        })
+                        .map_or(rest.len(), |(offset, _)| offset);
+                    (kind, length)
+                }
+            };
+
+            trivia.push(Trivia {
+                kind,
+                text: &self.source[position..position + length],
+                span: Span {
+                    start: position,
+                    end: position + length,
+                },
+            });
+            position += length;
+        }
+
+        trivia
+    }
+
+    /// The comments scanned so far, in source order
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// The errors reported so far, in source order
+    pub fn errors(&self) -> &[LexerError] {
+        &self.errors
+    }
+
+    /// Skips a leading `#!` line so scripts can be made executable with
+    /// `#!/usr/bin/env loxite`. The newline is left for `tokenize` to count.
+    fn skip_shebang(&mut self) {
+        if self.current != 0 || self.peek() != '#' || self.peek_next() != '!' {
+            return;
+        }
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
     }
 
     fn tokenize(&mut self) {
         match self.advance() {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(TokenType::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string();
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.add_token(TokenType::RightBrace)
+                }
+                None => self.add_token(TokenType::RightBrace),
+            },
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
             '!' => match self.match_next_char('=') {
                 true => self.add_token(TokenType::BangEqual),
                 false => self.add_token(TokenType::Bang),
@@ -76,42 +260,73 @@ impl<'a> Lexer<'a> {
                 true => self.add_token(TokenType::GreaterEqual),
                 false => self.add_token(TokenType::Greater),
             },
-            '/' => match self.match_next_char('/') {
-                true => {
+            '/' => {
+                if self.match_next_char('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_comment(self.line);
+                } else if self.match_next_char('*') {
+                    self.block_comment();
+                } else {
+                    self.add_token(TokenType::Slash);
                 }
-                false => self.add_token(TokenType::Slash),
-            },
+            }
             ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            '\n' => self.newline(),
             '"' => self.string(),
             '0'..='9' => self.number(),
             ch => {
                 if self.is_alpha(ch) {
                     self.identifier();
                 } else {
-                    LoxiteError::Lexer(LexerError {
-                        line: self.line,
-                        message: "Unexpected character".to_string(),
-                    })
-                    .print();
-                    self.had_error = true;
+                    self.error(self.line, self.start, "Unexpected character");
                 }
             }
         }
     }
 
+    /// Reports an error at the character starting at offset `position`, which
+    /// is on `line`
+    fn error(&mut self, line: usize, position: usize, message: &str) {
+        let error = LexerError {
+            line,
+            column: self.column(line, position),
+            message: message.to_string(),
+        };
+        if !self.quiet {
+            LoxiteError::Lexer(error.clone()).print();
+        }
+        self.errors.push(error);
+        self.had_error = true;
+    }
+
+    fn column(&self, line: usize, position: usize) -> usize {
+        let line_start = if line == self.line {
+            self.line_start
+        } else {
+            self.source[..position]
+                .rfind('\n')
+                .map_or(0, |newline| newline + 1)
+        };
+        self.source[line_start..position].chars().count() + 1
+    }
+
+    /// Called after consuming a `\n`
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn match_next_char(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
         }
-        if self.source[self.current] != expected {
+        if self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.current += expected.len_utf8();
         true
     }
 
@@ -120,65 +335,254 @@ impl<'a> Lexer<'a> {
     }
 
     fn add_token_with_literal(&mut self, token_type: TokenType, literal: TokenLiteral) {
-        let text = self.source[self.start..self.current]
-            .iter()
-            .collect::<String>();
-
-        self.tokens.push(Token {
+        self.next_token = Some(Token {
             token_type,
-            lexeme: text,
+            lexeme: Cow::Borrowed(&self.source[self.start..self.current]),
             literal,
             line: self.line,
+            span: Span {
+                start: self.start,
+                end: self.current,
+            },
         });
     }
 
+    fn add_comment(&mut self, line: usize) {
+        let text = &self.source[self.start..self.current];
+
+        self.comments.push(Comment {
+            text: text.trim_end().to_string(),
+            line,
+            span: Span {
+                start: self.start,
+                end: self.current,
+            },
+        });
+    }
+
+    /// Skips a `/* ... */` comment, which may contain other block comments.
+    /// The opening `/*` has already been consumed.
+    fn block_comment(&mut self) {
+        let start_line = self.line;
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                self.error(start_line, self.start, "Unterminated block comment");
+                return;
+            }
+
+            match self.advance() {
+                '\n' => self.newline(),
+                '/' if self.match_next_char('*') => depth += 1,
+                '*' if self.match_next_char('/') => depth -= 1,
+                _ => {}
+            }
+        }
+
+        self.add_comment(start_line);
+    }
+
+    /// Scans string text up to the closing `"`, or up to a `${` that embeds
+    /// an expression. In the latter case the text is emitted as an
+    /// `Interpolation` token, the expression is lexed as usual, and the `}`
+    /// closing it calls back in here for the rest of the string.
     fn string(&mut self) {
+        let start_line = self.line;
+        let mut value = String::new();
+
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.peek() == '$' && self.peek_next() == '{' {
+                self.current += 2;
+                self.interpolations.push(0);
+                let literal = TokenLiteral::String(value);
+                self.add_token_with_literal(TokenType::Interpolation, literal);
+                return;
+            }
+
+            match self.advance() {
+                '\n' => {
+                    self.newline();
+                    value.push('\n');
+                }
+                '\\' => {
+                    if let Some(ch) = self.escape() {
+                        value.push(ch);
+                    }
+                }
+                ch => value.push(ch),
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            LoxiteError::Lexer(LexerError {
-                line: self.line,
-                message: "Unterminaded string".to_string(),
-            })
-            .print();
-            self.had_error = true;
+            self.error(start_line, self.start, "Unterminated string");
             return;
         }
 
         self.advance();
 
-        let value = self.source[self.start + 1..self.current - 1]
-            .iter()
-            .collect::<String>();
-
         let literal = TokenLiteral::String(value);
         self.add_token_with_literal(TokenType::String, literal);
     }
 
-    fn number(&mut self) {
-        while self.is_digit(self.peek()) {
-            self.advance();
+    /// Decodes the escape sequence after a `\` in a string. Invalid sequences
+    /// are reported and skipped.
+    fn escape(&mut self) -> Option<char> {
+        let backslash = self.current - 1;
+        if self.is_at_end() {
+            return None;
+        }
+
+        let ch = match self.advance() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            'u' => return self.unicode_escape(backslash),
+            '\n' => {
+                self.error(
+                    self.line,
+                    backslash,
+                    "Invalid escape sequence at end of line",
+                );
+                self.newline();
+                return None;
+            }
+            ch => {
+                let message = format!("Invalid escape sequence '\\{}'", ch);
+                self.error(self.line, backslash, &message);
+                return None;
+            }
+        };
+        Some(ch)
+    }
+
+    /// Decodes the `{1F600}` part of a `\u{1F600}` escape
+    fn unicode_escape(&mut self, backslash: usize) -> Option<char> {
+        if !self.match_next_char('{') {
+            self.error(self.line, backslash, "Expected '{' after '\\u'");
+            return None;
         }
-        if self.peek() == '.' && self.is_digit(self.peek_next()) {
+
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
             self.advance();
-            while self.is_digit(self.peek()) {
-                self.advance();
+        }
+        let digits = &self.source[digits_start..self.current];
+
+        if !self.match_next_char('}') {
+            self.error(self.line, backslash, "Unterminated unicode escape");
+            return None;
+        }
+
+        let ch = match digits.len() {
+            1..=6 => u32::from_str_radix(digits, 16)
+                .ok()
+                .and_then(char::from_u32),
+            _ => None,
+        };
+        if ch.is_none() {
+            let message = format!("Invalid unicode escape '\\u{{{}}}'", digits);
+            self.error(self.line, backslash, &message);
+        }
+        ch
+    }
+
+    /// Scans a number literal: `123`, `45.67`, `1e-9`, `0xFF`, `0b1010` or
+    /// `0o17`, with `_` allowed between digits. The first digit has already
+    /// been consumed.
+    fn number(&mut self) {
+        match self.number_value() {
+            Ok(value) => {
+                let literal = TokenLiteral::Number(value);
+                self.add_token_with_literal(TokenType::Number, literal);
+            }
+            Err(message) => {
+                // Skip the rest of the literal so it is not lexed as more tokens
+                while self.is_alpha_numeric(self.peek()) {
+                    self.advance();
+                }
+                self.error(self.line, self.start, &message);
             }
         }
+    }
 
-        let value = self.source[self.start..self.current]
-            .iter()
-            .collect::<String>()
-            .parse::<f64>()
-            .unwrap();
+    fn number_value(&mut self) -> Result<f64, String> {
+        let radix = match (&self.source[self.start..self.current], self.peek()) {
+            ("0", 'x' | 'X') => Some(16),
+            ("0", 'o' | 'O') => Some(8),
+            ("0", 'b' | 'B') => Some(2),
+            _ => None,
+        };
+
+        let value = match radix {
+            Some(radix) => {
+                self.advance();
+                let digits_start = self.current;
+                if self.digits(radix)? == 0 {
+                    let prefix = &self.source[self.start..self.current];
+                    return Err(format!("Expected digits after '{}'", prefix));
+                }
+                // Built up as a float so long literals lose precision instead
+                // of overflowing
+                self.source[digits_start..self.current]
+                    .chars()
+                    .filter_map(|ch| ch.to_digit(radix))
+                    .fold(0.0, |value, digit| value * radix as f64 + digit as f64)
+            }
+            None => {
+                self.digits(10)?;
+                if self.peek() == '.' && self.is_digit(self.peek_next()) {
+                    self.advance();
+                    self.digits(10)?;
+                }
+                if matches!(self.peek(), 'e' | 'E') {
+                    self.advance();
+                    if matches!(self.peek(), '+' | '-') {
+                        self.advance();
+                    }
+                    if self.digits(10)? == 0 {
+                        return Err("Expected digits in exponent".to_string());
+                    }
+                }
 
-        let literal = TokenLiteral::Number(value);
-        self.add_token_with_literal(TokenType::Number, literal);
+                let text = self.source[self.start..self.current].replace('_', "");
+                text.parse::<f64>()
+                    .map_err(|_| format!("Invalid number literal '{}'", text))?
+            }
+        };
+
+        if self.is_alpha_numeric(self.peek()) {
+            return Err(format!("Unexpected '{}' in number literal", self.peek()));
+        }
+        Ok(value)
+    }
+
+    /// Consumes digits in `radix`, returning how many there were. A `_` may
+    /// separate two digits.
+    fn digits(&mut self, radix: u32) -> Result<usize, String> {
+        let mut count = 0;
+        loop {
+            let ch = self.peek();
+            if ch.is_digit(radix) {
+                count += 1;
+            } else if ch == '_' {
+                let after_digit = self.source[..self.current]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|previous| previous.is_digit(radix));
+                if !after_digit || !self.peek_next().is_digit(radix) {
+                    self.advance();
+                    return Err("Digit separator '_' must be between digits".to_string());
+                }
+            } else {
+                return Ok(count);
+            }
+            self.advance();
+        }
     }
 
     fn identifier(&mut self) {
@@ -186,74 +590,350 @@ impl<'a> Lexer<'a> {
             self.advance();
         }
 
-        let text = self.source[self.start..self.current]
-            .iter()
-            .collect::<String>();
+        let text = &self.source[self.start..self.current];
 
-        let token_type = match self.keywords.get(text.as_str()) {
-            Some(value) => *value,
-            None => TokenType::Identifier,
-        };
+        let token_type = keyword(text).unwrap_or(TokenType::Identifier);
 
         self.add_token(token_type);
     }
 
     fn advance(&mut self) -> char {
-        let ch = self.source[self.current];
-        self.current += 1;
+        let ch = self.peek();
+        self.current += ch.len_utf8();
         ch
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source[self.current]
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
-        }
-        return self.source[self.current + 1];
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn is_digit(&self, ch: char) -> bool {
-        ch >= '0' && ch <= '9'
+        ch.is_ascii_digit()
     }
 
+    /// Whether `ch` can start an identifier: `_` or a Unicode `XID_Start` character
     fn is_alpha(&self, ch: char) -> bool {
-        (ch >= 'a' && ch <= 'z') || (ch >= 'A' && ch <= 'Z') || ch == '_'
+        ch == '_' || unicode_ident::is_xid_start(ch)
     }
 
+    /// Whether `ch` can continue an identifier: a Unicode `XID_Continue`
+    /// character, which covers digits, `_` and combining marks
     fn is_alpha_numeric(&self, ch: char) -> bool {
-        self.is_alpha(ch) || self.is_digit(ch)
+        unicode_ident::is_xid_continue(ch)
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
+}
+
+/// The keyword spelled by `text`, if there is one. Like the trie in clox, this
+/// switches on the first letter or two, so most identifiers are ruled out
+/// without comparing any strings and the rest need a single comparison.
+fn keyword(text: &str) -> Option<TokenType> {
+    let bytes = text.as_bytes();
+    let (expected, token_type) = match (bytes.first()?, bytes.get(1)) {
+        (b'a', _) => ("and", TokenType::And),
+        (b'c', _) => ("class", TokenType::Class),
+        (b'e', _) => ("else", TokenType::Else),
+        (b'f', Some(b'a')) => ("false", TokenType::False),
+        (b'f', Some(b'o')) => ("for", TokenType::For),
+        (b'f', Some(b'u')) => ("fun", TokenType::Fun),
+        (b'i', _) => ("if", TokenType::If),
+        (b'n', _) => ("nil", TokenType::Nil),
+        (b'o', _) => ("or", TokenType::Or),
+        (b'p', _) => ("print", TokenType::Print),
+        (b'r', _) => ("return", TokenType::Return),
+        (b's', _) => ("super", TokenType::Super),
+        (b't', Some(b'h')) => ("this", TokenType::This),
+        (b't', Some(b'r')) => ("true", TokenType::True),
+        (b'v', _) => ("var", TokenType::Var),
+        (b'w', _) => ("while", TokenType::While),
+        _ => return None,
+    };
+    (text == expected).then_some(token_type)
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.skip_shebang();
+        while !self.is_at_end() {
+            self.start = self.current;
+            self.tokenize();
+            if let Some(token) = self.next_token.take() {
+                return Some(token);
+            }
+        }
 
-    fn get_keywords() -> HashMap<&'a str, TokenType> {
-        let mut keywords: HashMap<&'a str, TokenType> = HashMap::new();
-
-        keywords.insert("and", TokenType::And);
-        keywords.insert("class", TokenType::Class);
-        keywords.insert("else", TokenType::Else);
-        keywords.insert("false", TokenType::False);
-        keywords.insert("for", TokenType::For);
-        keywords.insert("fun", TokenType::Fun);
-        keywords.insert("if", TokenType::If);
-        keywords.insert("nil", TokenType::Nil);
-        keywords.insert("or", TokenType::Or);
-        keywords.insert("print", TokenType::Print);
-        keywords.insert("return", TokenType::Return);
-        keywords.insert("super", TokenType::Super);
-        keywords.insert("this", TokenType::This);
-        keywords.insert("true", TokenType::True);
-        keywords.insert("var", TokenType::Var);
-        keywords.insert("while", TokenType::While);
-
-        keywords
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        Some(Token {
+            token_type: TokenType::EOF,
+            lexeme: Cow::Borrowed(""),
+            literal: TokenLiteral::Empty,
+            line: self.line,
+            span: Span {
+                start: self.current,
+                end: self.current,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{
+        lexer::{keyword, Lexer},
+        token::{TokenLiteral, TokenType},
+    };
+
+    #[test]
+    fn test_keywords() {
+        let keywords = [
+            ("and", TokenType::And),
+            ("class", TokenType::Class),
+            ("else", TokenType::Else),
+            ("false", TokenType::False),
+            ("for", TokenType::For),
+            ("fun", TokenType::Fun),
+            ("if", TokenType::If),
+            ("nil", TokenType::Nil),
+            ("or", TokenType::Or),
+            ("print", TokenType::Print),
+            ("return", TokenType::Return),
+            ("super", TokenType::Super),
+            ("this", TokenType::This),
+            ("true", TokenType::True),
+            ("var", TokenType::Var),
+            ("while", TokenType::While),
+        ];
+        for (text, token_type) in keywords {
+            assert_eq!(keyword(text), Some(token_type), "{}", text);
+            let tokens = Lexer::new(text).scan_tokens();
+            assert_eq!(tokens[0].token_type, token_type, "{}", text);
+        }
+
+        for text in [
+            "", "a", "an", "andy", "f", "fa", "fork", "t", "th", "tru", "Var", "éclair",
+        ] {
+            assert_eq!(keyword(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_lexes_lazily_and_borrows_lexemes() {
+        let source = String::from("1 + \"two\"");
+        let mut lexer = Lexer::new(&source);
+
+        let first = lexer.next().unwrap();
+        assert_eq!(first.token_type, TokenType::Number);
+        assert!(matches!(first.lexeme, Cow::Borrowed("1")));
+
+        let rest: Vec<TokenType> = lexer.by_ref().map(|token| token.token_type).collect();
+        assert_eq!(
+            rest,
+            vec![TokenType::Plus, TokenType::String, TokenType::EOF]
+        );
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_skips_shebang_line() {
+        let mut lexer = Lexer::new("#!/usr/bin/env loxite\n1 + 2");
+        let tokens = lexer.scan_tokens();
+
+        assert!(!lexer.had_error);
+        let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Number,
+                TokenType::Plus,
+                TokenType::Number,
+                TokenType::EOF
+            ]
+        );
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let source = "/* a\n/* nested\n*/ still comment */ 1 /**/ +\n/*\n*/ 2";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan_tokens();
+
+        assert!(!lexer.had_error);
+        let lexemes: Vec<(&str, usize)> = tokens
+            .iter()
+            .map(|token| (token.lexeme.as_ref(), token.line))
+            .collect();
+        assert_eq!(lexemes, vec![("1", 3), ("+", 3), ("2", 5), ("", 5)]);
+
+        let comments: Vec<(usize, usize)> = lexer
+            .comments()
+            .iter()
+            .map(|comment| (comment.line, comment.end_line()))
+            .collect();
+        assert_eq!(comments, vec![(1, 3), (3, 3), (4, 5)]);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut lexer = Lexer::new("1 /* a /* b */\n");
+        let tokens = lexer.scan_tokens();
+        assert!(lexer.had_error);
+        assert_eq!(tokens.len(), 2);
+    }
+
+    #[test]
+    fn test_hash_outside_first_line_is_an_error() {
+        let mut lexer = Lexer::new("1\n#!");
+        lexer.scan_tokens();
+        assert!(lexer.had_error);
+        let error = &lexer.errors()[0];
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(error.message, "Unexpected character");
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let mut lexer = Lexer::new(r#""tab\there \"q\" \\ \u{e9}\u{1F600}\n""#);
+        let tokens = lexer.scan_tokens();
+
+        assert!(!lexer.had_error);
+        assert_eq!(
+            tokens[0].literal,
+            TokenLiteral::String("tab\there \"q\" \\ é😀\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut lexer = Lexer::new(r#""a ${1 + "b ${2}"} c\${d}""#);
+        let tokens = lexer.scan_tokens();
+
+        assert!(!lexer.had_error);
+        let lexemes: Vec<(TokenType, &str)> = tokens
+            .iter()
+            .map(|token| (token.token_type, token.lexeme.as_ref()))
+            .collect();
+        assert_eq!(
+            lexemes,
+            vec![
+                (TokenType::Interpolation, "\"a ${"),
+                (TokenType::Number, "1"),
+                (TokenType::Plus, "+"),
+                (TokenType::Interpolation, "\"b ${"),
+                (TokenType::Number, "2"),
+                (TokenType::String, "}\""),
+                (TokenType::String, "} c\\${d}\""),
+                (TokenType::EOF, ""),
+            ]
+        );
+        assert_eq!(tokens[0].literal, TokenLiteral::String("a ".to_string()));
+        assert_eq!(
+            tokens[6].literal,
+            TokenLiteral::String(" c${d}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let cases = [
+            ("123", 123.0),
+            ("45.67", 45.67),
+            ("1_000_000", 1_000_000.0),
+            ("0xFF", 255.0),
+            ("0Xdead_BEEF", 3_735_928_559.0),
+            ("0b1010", 10.0),
+            ("0o17", 15.0),
+            ("1e-9", 1e-9),
+            ("2.5E+3", 2500.0),
+            ("1_0e1_0", 1e11),
+            ("007", 7.0),
+        ];
+        for (source, expected) in cases {
+            let mut lexer = Lexer::new(source);
+            let tokens = lexer.scan_tokens();
+            assert!(!lexer.had_error, "{}", source);
+            assert_eq!(
+                tokens[0].literal,
+                TokenLiteral::Number(expected),
+                "{}",
+                source
+            );
+            assert_eq!(tokens[0].lexeme, source);
+        }
+    }
+
+    #[test]
+    fn test_malformed_number_literals() {
+        for source in [
+            "0x", "0b", "0o_1", "1e", "1e+", "1__0", "1_", "1_e5", "0b102", "0xFG", "12abc",
+        ] {
+            let mut lexer = Lexer::new(source);
+            let tokens = lexer.scan_tokens();
+            assert!(lexer.had_error, "{}", source);
+            assert_eq!(tokens.len(), 1, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let mut lexer = Lexer::new("café 数学 _x1 x\u{303} Ωmega \"é数😀\"");
+        let tokens = lexer.scan_tokens();
+
+        assert!(!lexer.had_error);
+        let identifiers: Vec<&str> = tokens[..5]
+            .iter()
+            .map(|token| {
+                assert_eq!(token.token_type, TokenType::Identifier);
+                token.lexeme.as_ref()
+            })
+            .collect();
+        assert_eq!(
+            identifiers,
+            vec!["café", "数学", "_x1", "x\u{303}", "Ωmega"]
+        );
+        assert_eq!(tokens[5].literal, TokenLiteral::String("é数😀".to_string()));
+        // Spans are byte offsets
+        assert_eq!((tokens[1].span.start, tokens[1].span.end), (6, 12));
+    }
+
+    #[test]
+    fn test_emoji_is_not_an_identifier() {
+        let mut lexer = Lexer::new("a😀");
+        lexer.scan_tokens();
+        assert!(lexer.had_error);
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let sources = [
+            r#""\q""#,
+            r#""\u41""#,
+            r#""\u{}""#,
+            r#""\u{1234567}""#,
+            r#""\u{D800}""#,
+            r#""\u{41"#,
+            "\"a\\\nb\"",
+        ];
+        for source in sources {
+            let mut lexer = Lexer::new(source);
+            lexer.scan_tokens();
+            assert!(lexer.had_error, "{}", source);
+        }
     }
 }
@@ -1,7 +1,17 @@
+pub mod ast_json;
 pub mod ast_printer;
+pub mod cst;
+pub mod dap;
+pub mod dot_printer;
 pub mod error;
 pub mod expr;
+pub mod formatter;
+pub mod highlight;
+pub mod incremental;
 pub mod interpreter;
+pub mod json;
 pub mod lexer;
+pub mod lsp;
 pub mod parser;
 pub mod token;
+pub mod visitor;
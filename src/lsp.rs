@@ -0,0 +1,636 @@
+//! A Language Server Protocol server for `loxite lsp`, speaking JSON-RPC over
+//! stdio.
+//!
+//! It publishes the lexer and parser errors of each open document as
+//! diagnostics, formats documents with `Formatter`, and on hover describes
+//! literals and the operation under the cursor, without evaluating it. Lox
+//! programs here are single expressions without declarations, so there are
+//! no definitions or document symbols to offer and those capabilities are not
+//! advertised.
+//!
+//! Positions follow the protocol: lines are 0-based and characters count
+//! UTF-16 code units.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    error::{LexerError, ParserError},
+    expr::Expr,
+    formatter::Formatter,
+    incremental::TextEdit,
+    json::JsonValue,
+    lexer::Lexer,
+    parser::Parser,
+    token::{Span, TokenLiteral, TokenType},
+};
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+/// `DiagnosticSeverity.Error`
+const SEVERITY_ERROR: f64 = 1.0;
+/// `TextDocumentSyncKind.Incremental`
+const SYNC_INCREMENTAL: f64 = 2.0;
+
+/// The open documents and the state of the session
+#[derive(Default)]
+pub struct LanguageServer {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the messages read from `input` until the client sends `exit`
+    /// or closes the stream. Returns the exit code: 0 if the client asked
+    /// for a shutdown first, 1 otherwise.
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+        while let Some(body) = read_message(&mut input)? {
+            let replies = match JsonValue::parse(&body) {
+                Ok(message)
+                    if message.get("method").and_then(JsonValue::as_str) == Some("exit") =>
+                {
+                    break;
+                }
+                Ok(message) => self.handle(&message),
+                Err(err) => vec![error_response(JsonValue::Null, PARSE_ERROR, &err.message)],
+            };
+            for reply in replies {
+                write_message(&mut output, &reply)?;
+            }
+            output.flush()?;
+        }
+
+        Ok(if self.shutdown { 0 } else { 1 })
+    }
+
+    /// Handles one request or notification and returns the messages to send
+    /// back: the response to a request, and any notifications it causes
+    pub fn handle(&mut self, message: &JsonValue) -> Vec<JsonValue> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(JsonValue::as_str) else {
+            return vec![error_response(
+                id.unwrap_or(JsonValue::Null),
+                INVALID_REQUEST,
+                "Missing method",
+            )];
+        };
+        let params = message.get("params").unwrap_or(&JsonValue::Null);
+
+        let result = match method {
+            "initialize" => Ok(Some(capabilities())),
+            "initialized" | "$/cancelRequest" | "$/setTrace" => Ok(None),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Some(JsonValue::Null))
+            }
+            "textDocument/didOpen" => {
+                return self.did_open(params).unwrap_or_default();
+            }
+            "textDocument/didChange" => {
+                return self.did_change(params).unwrap_or_default();
+            }
+            "textDocument/didClose" => {
+                return self.did_close(params).unwrap_or_default();
+            }
+            "textDocument/hover" => self.hover(params).ok_or(INVALID_PARAMS).map(Some),
+            "textDocument/formatting" => self.formatting(params).ok_or(INVALID_PARAMS).map(Some),
+            _ => Err(METHOD_NOT_FOUND),
+        };
+
+        // Notifications get no response, even when they fail
+        let Some(id) = id else {
+            return vec![];
+        };
+        match result {
+            Ok(result) => vec![JsonValue::object(vec![
+                ("jsonrpc", JsonValue::String("2.0".to_string())),
+                ("id", id),
+                ("result", result.unwrap_or(JsonValue::Null)),
+            ])],
+            Err(METHOD_NOT_FOUND) => vec![error_response(
+                id,
+                METHOD_NOT_FOUND,
+                &format!("Unknown method {}", method),
+            )],
+            Err(code) => vec![error_response(id, code, "Invalid params")],
+        }
+    }
+
+    fn did_open(&mut self, params: &JsonValue) -> Option<Vec<JsonValue>> {
+        let document = params.get("textDocument")?;
+        let uri = document.get("uri")?.as_str()?;
+        let text = document.get("text")?.as_str()?;
+        self.documents.insert(uri.to_string(), text.to_string());
+        Some(vec![self.diagnostics(uri)])
+    }
+
+    /// Applies the changes in order. Each one either replaces a range or,
+    /// without a range, the whole document.
+    fn did_change(&mut self, params: &JsonValue) -> Option<Vec<JsonValue>> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let source = self.documents.get_mut(uri)?;
+        for change in params.get("contentChanges")?.as_array()? {
+            let text = change.get("text")?.as_str()?;
+            *source = match change.get("range") {
+                Some(range) => {
+                    let start = offset(source, range.get("start")?)?;
+                    let end = offset(source, range.get("end")?)?;
+                    TextEdit {
+                        range: start..end.max(start),
+                        replacement: text.to_string(),
+                    }
+                    .apply(source)
+                }
+                None => text.to_string(),
+            };
+        }
+        Some(vec![self.diagnostics(uri)])
+    }
+
+    /// Forgets the document and clears its diagnostics
+    fn did_close(&mut self, params: &JsonValue) -> Option<Vec<JsonValue>> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        self.documents.remove(uri);
+        Some(vec![publish_diagnostics(uri, vec![])])
+    }
+
+    /// A `textDocument/publishDiagnostics` notification with the lexer
+    /// errors of the document, or its parser errors if it lexes cleanly
+    fn diagnostics(&self, uri: &str) -> JsonValue {
+        let source = &self.documents[uri];
+        let mut lexer = Lexer::new(source).quiet();
+        let tokens = lexer.scan_tokens();
+
+        let diagnostics = if lexer.had_error {
+            lexer
+                .errors()
+                .iter()
+                .map(|error| lexer_diagnostic(source, error))
+                .collect()
+        } else {
+            let mut parser = Parser::new(tokens).quiet();
+            parser.parser();
+            parser
+                .errors()
+                .iter()
+                .map(|error| parser_diagnostic(source, error))
+                .collect()
+        };
+
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    /// Describes the token under the cursor: the value of a literal, or the
+    /// kind and code of the operation whose operator it is
+    fn hover(&self, params: &JsonValue) -> Option<JsonValue> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let source = self.documents.get(uri)?;
+        let offset = offset(source, params.get("position")?)?;
+
+        let mut lexer = Lexer::new(source).quiet();
+        let tokens = lexer.scan_tokens();
+        let Some(token) = tokens.iter().find(|token| {
+            token.token_type != TokenType::EOF
+                && token.span.start <= offset
+                && offset < token.span.end
+        }) else {
+            return Some(JsonValue::Null);
+        };
+
+        let contents = match (&token.token_type, &token.literal) {
+            (TokenType::Number, TokenLiteral::Number(value)) => format!("number `{}`", value),
+            (TokenType::String, literal @ TokenLiteral::String(_)) => {
+                format!("string `{}`", literal.to_source())
+            }
+            (TokenType::True | TokenType::False, _) => "boolean".to_string(),
+            (TokenType::Nil, _) => "nil".to_string(),
+            (TokenType::Interpolation, _) => "interpolated string".to_string(),
+            _ if lexer.had_error => return Some(JsonValue::Null),
+            _ => {
+                let span = token.span;
+                let Some(expression) = Parser::new(tokens.clone()).quiet().parser() else {
+                    return Some(JsonValue::Null);
+                };
+                let Some(operation) = operation_at(&expression, span) else {
+                    return Some(JsonValue::Null);
+                };
+                // Only the syntax is described: evaluating here would run the
+                // user's code on every hover
+                let kind = match operation {
+                    Expr::Unary(_) => "unary",
+                    _ => "binary",
+                };
+                let code = Formatter.format_expr(operation);
+                format!("{} `{}` operation: `{}`", kind, token.lexeme, code)
+            }
+        };
+
+        Some(JsonValue::object(vec![
+            (
+                "contents",
+                JsonValue::object(vec![
+                    ("kind", JsonValue::String("markdown".to_string())),
+                    ("value", JsonValue::String(contents)),
+                ]),
+            ),
+            ("range", range(source, token.span)),
+        ]))
+    }
+
+    /// One edit replacing the whole document with its formatted text, none if
+    /// it is already formatted or does not parse
+    fn formatting(&self, params: &JsonValue) -> Option<JsonValue> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let source = self.documents.get(uri)?;
+
+        let edits = match Formatter.format_quietly(source) {
+            Some(formatted) if formatted != *source => vec![JsonValue::object(vec![
+                (
+                    "range",
+                    range(
+                        source,
+                        Span {
+                            start: 0,
+                            end: source.len(),
+                        },
+                    ),
+                ),
+                ("newText", JsonValue::String(formatted)),
+            ])],
+            _ => vec![],
+        };
+        Some(JsonValue::Array(edits))
+    }
+}
+
+fn capabilities() -> JsonValue {
+    JsonValue::object(vec![
+        (
+            "capabilities",
+            JsonValue::object(vec![
+                (
+                    "textDocumentSync",
+                    JsonValue::object(vec![
+                        ("openClose", JsonValue::Boolean(true)),
+                        ("change", JsonValue::Number(SYNC_INCREMENTAL)),
+                    ]),
+                ),
+                ("hoverProvider", JsonValue::Boolean(true)),
+                ("documentFormattingProvider", JsonValue::Boolean(true)),
+            ]),
+        ),
+        (
+            "serverInfo",
+            JsonValue::object(vec![
+                ("name", JsonValue::String("loxite".to_string())),
+                (
+                    "version",
+                    JsonValue::String(env!("CARGO_PKG_VERSION").to_string()),
+                ),
+            ]),
+        ),
+    ])
+}
+
+/// The unary or binary expression whose operator is the token at `span`
+fn operation_at<'e, 'a>(expr: &'e Expr<'a>, span: Span) -> Option<&'e Expr<'a>> {
+    match expr {
+        Expr::Unary(unary) if unary.operator.span == span => Some(expr),
+        Expr::Binary(binary) if binary.operator.span == span => Some(expr),
+        Expr::Unary(unary) => operation_at(&unary.right, span),
+        Expr::Binary(binary) => {
+            operation_at(&binary.left, span).or_else(|| operation_at(&binary.right, span))
+        }
+        Expr::Grouping(grouping) => operation_at(&grouping.expression, span),
+        Expr::Conditional(conditional) => operation_at(&conditional.condition, span)
+            .or_else(|| operation_at(&conditional.then_branch, span))
+            .or_else(|| operation_at(&conditional.else_branch, span)),
+        Expr::Interpolation(interpolation) => interpolation
+            .expressions
+            .iter()
+            .find_map(|expression| operation_at(expression, span)),
+        Expr::Literal(_) => None,
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<JsonValue>) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", JsonValue::String("2.0".to_string())),
+        (
+            "method",
+            JsonValue::String("textDocument/publishDiagnostics".to_string()),
+        ),
+        (
+            "params",
+            JsonValue::object(vec![
+                ("uri", JsonValue::String(uri.to_string())),
+                ("diagnostics", JsonValue::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+/// Lexer errors point at a single character, given by line and column
+fn lexer_diagnostic(source: &str, error: &LexerError) -> JsonValue {
+    let line_start = source
+        .split_inclusive('\n')
+        .take(error.line - 1)
+        .map(str::len)
+        .sum::<usize>();
+    let mut chars = source[line_start..].char_indices().skip(error.column - 1);
+    let start = chars
+        .next()
+        .map_or(source.len(), |(offset, _)| line_start + offset);
+    let end = chars
+        .next()
+        .map_or(source.len(), |(offset, _)| line_start + offset);
+    diagnostic(source, Span { start, end }, &error.message)
+}
+
+/// Parser errors cover the token they were found at
+fn parser_diagnostic(source: &str, error: &ParserError) -> JsonValue {
+    diagnostic(source, error.token.span, &error.message)
+}
+
+fn diagnostic(source: &str, span: Span, message: &str) -> JsonValue {
+    JsonValue::object(vec![
+        ("range", range(source, span)),
+        ("severity", JsonValue::Number(SEVERITY_ERROR)),
+        ("source", JsonValue::String("loxite".to_string())),
+        ("message", JsonValue::String(message.to_string())),
+    ])
+}
+
+fn range(source: &str, span: Span) -> JsonValue {
+    JsonValue::object(vec![
+        ("start", position(source, span.start)),
+        ("end", position(source, span.end)),
+    ])
+}
+
+/// The protocol position of byte `offset`
+fn position(source: &str, offset: usize) -> JsonValue {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    JsonValue::object(vec![
+        (
+            "line",
+            JsonValue::Number(before.matches('\n').count() as f64),
+        ),
+        (
+            "character",
+            JsonValue::Number(before[line_start..].encode_utf16().count() as f64),
+        ),
+    ])
+}
+
+/// The byte offset of a protocol position. Positions past the end of a line
+/// are clamped to it, and lines past the end to the end of the source.
+fn offset(source: &str, position: &JsonValue) -> Option<usize> {
+    let line = position.get("line")?.as_f64()? as usize;
+    let character = position.get("character")?.as_f64()? as usize;
+
+    let mut lines = source.split_inclusive('\n');
+    let line_start = lines.by_ref().take(line).map(str::len).sum::<usize>();
+    let text = source[line_start..].split('\n').next().unwrap_or_default();
+
+    let mut units = 0;
+    for (offset, ch) in text.char_indices() {
+        if units >= character {
+            return Some(line_start + offset);
+        }
+        units += ch.len_utf16();
+    }
+    Some(line_start + text.len())
+}
+
+fn error_response(id: JsonValue, code: i32, message: &str) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", JsonValue::String("2.0".to_string())),
+        ("id", id),
+        (
+            "error",
+            JsonValue::object(vec![
+                ("code", JsonValue::Number(code as f64)),
+                ("message", JsonValue::String(message.to_string())),
+            ]),
+        ),
+    ])
+}
+
+/// Reads the body of the next message, framed by a `Content-Length` header.
+/// Returns `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message(output: &mut impl Write, message: &JsonValue) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use crate::{
+        json::JsonValue,
+        lsp::{read_message, LanguageServer},
+    };
+
+    /// Runs the server over a scripted transcript of client messages and
+    /// returns its exit code and the messages it sent back
+    fn transcript(messages: &[&str]) -> (i32, Vec<JsonValue>) {
+        let input: String = messages
+            .iter()
+            .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+            .collect();
+        let mut output = vec![];
+        let code = LanguageServer::new()
+            .serve(input.as_bytes(), &mut output)
+            .unwrap();
+
+        let mut reader = BufReader::new(output.as_slice());
+        let mut replies = vec![];
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            replies.push(JsonValue::parse(&body).unwrap());
+        }
+        (code, replies)
+    }
+
+    fn open(text: &str) -> String {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///a.lox","languageId":"lox","version":1,"text":{}}}}}}}"#,
+            JsonValue::String(text.to_string())
+        )
+    }
+
+    fn diagnostics(reply: &JsonValue) -> Vec<String> {
+        reply
+            .get("params")
+            .unwrap()
+            .get("diagnostics")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|diagnostic| {
+                let start = diagnostic.get("range").unwrap().get("start").unwrap();
+                format!(
+                    "{}:{} {}",
+                    start.get("line").unwrap(),
+                    start.get("character").unwrap(),
+                    diagnostic.get("message").unwrap().as_str().unwrap()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let (code, replies) = transcript(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/definition","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(replies.len(), 3);
+
+        let capabilities = replies[0]
+            .get("result")
+            .unwrap()
+            .get("capabilities")
+            .unwrap();
+        assert_eq!(
+            capabilities.get("hoverProvider"),
+            Some(&JsonValue::Boolean(true))
+        );
+        let error = replies[1].get("error").unwrap();
+        assert_eq!(error.get("code").unwrap().as_f64(), Some(-32601.0));
+        assert_eq!(
+            replies[2].to_string(),
+            r#"{"jsonrpc":"2.0","id":3,"result":null}"#
+        );
+
+        let (code, _) = transcript(&[r#"{"jsonrpc":"2.0","method":"exit"}"#]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (_, replies) = transcript(&[
+            &open("1 +\n  \"é\" @"),
+            r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.lox","version":2},"contentChanges":[{"range":{"start":{"line":1,"character":2},"end":{"line":1,"character":7}},"text":""}]}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.lox","version":3},"contentChanges":[{"text":"* 1"}]}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.lox","version":4},"contentChanges":[{"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":2}},"text":""}]}}"#,
+        ]);
+        assert_eq!(
+            replies.iter().map(diagnostics).collect::<Vec<_>>(),
+            vec![
+                vec!["1:6 Unexpected character".to_string()],
+                vec!["1:2 Expected expression.".to_string()],
+                vec!["0:0 Missing left operand.".to_string()],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let hover = |character: usize| {
+            format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"textDocument/hover","params":{{"textDocument":{{"uri":"file:///a.lox"}},"position":{{"line":0,"character":{}}}}}}}"#,
+                character
+            )
+        };
+        let (_, replies) = transcript(&[
+            &open("0x10 * (2 + 3) - \"a\""),
+            &hover(1),
+            &hover(5),
+            &hover(10),
+            &hover(15),
+            &hover(13),
+        ]);
+        let contents: Vec<String> = replies[1..]
+            .iter()
+            .map(|reply| match reply.get("result").unwrap() {
+                JsonValue::Null => "null".to_string(),
+                result => result
+                    .get("contents")
+                    .unwrap()
+                    .get("value")
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+            })
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                "number `16`",
+                "binary `*` operation: `0x10 * (2 + 3)`",
+                "binary `+` operation: `2 + 3`",
+                "binary `-` operation: `0x10 * (2 + 3) - \"a\"`",
+                "null",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_formatting() {
+        let (_, replies) = transcript(&[
+            &open("1+( 2 )"),
+            r#"{"jsonrpc":"2.0","id":"f","method":"textDocument/formatting","params":{"textDocument":{"uri":"file:///a.lox"},"options":{"tabSize":4,"insertSpaces":true}}}"#,
+        ]);
+        assert_eq!(
+            replies[1].get("result").unwrap().to_string(),
+            r#"[{"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":7}},"newText":"1 + (2)\n"}]"#
+        );
+    }
+
+    #[test]
+    fn test_no_formatting_with_leftover_tokens() {
+        let format = r#"{"jsonrpc":"2.0","id":"f","method":"textDocument/formatting","params":{"textDocument":{"uri":"file:///a.lox"},"options":{"tabSize":4,"insertSpaces":true}}}"#;
+        for source in ["1 2 + 3", "1 + 2 // a\n/* b */ 3", "(1) )"] {
+            let (_, replies) = transcript(&[&open(source), format]);
+            assert_eq!(replies[1].get("result").unwrap().to_string(), "[]");
+        }
+    }
+}
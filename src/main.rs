@@ -1,10 +1,23 @@
 use std::{
     env, fs,
-    io::{self, Write},
+    io::{self, Read, Write},
     process::exit,
 };
 
-use loxite::{ast_printer::AstPrinter, interpreter::Interpreter, lexer::Lexer, parser::Parser};
+use loxite::{
+    ast_json::JsonPrinter,
+    ast_printer::{AstPrinter, ExprPrinter, InfixPrinter, RpnPrinter},
+    dap::DebugAdapter,
+    dot_printer::DotPrinter,
+    expr::Expr,
+    formatter::Formatter,
+    highlight::{to_ansi, to_html},
+    interpreter::Interpreter,
+    json::{FromJson, JsonValue},
+    lexer::Lexer,
+    lsp::LanguageServer,
+    parser::Parser,
+};
 
 struct Loxite {
     interpreter: Interpreter,
@@ -13,11 +26,11 @@ struct Loxite {
 impl Loxite {
     pub fn new() -> Self {
         Self {
-            interpreter: Interpreter {},
+            interpreter: Interpreter::new(),
         }
     }
 
-    fn run(&self, source: &String) -> Option<i32> {
+    fn run(&mut self, source: &str) -> Option<i32> {
         let mut lexer = Lexer::new(source);
         let tokens = lexer.scan_tokens();
         if lexer.had_error {
@@ -25,28 +38,59 @@ impl Loxite {
         }
 
         let mut parser = Parser::new(tokens);
-        let expression = parser.parser();
-        if let None = expression {
+        let Some(expression) = parser.parser() else {
             return Some(65);
-        }
+        };
 
-        let expression = expression.unwrap();
-        let result_str = AstPrinter.get_expr_as_str(&expression);
-        println!("AST: {}", result_str);
+        self.interpret(&expression)
+    }
 
-        self.interpreter.interpreter(&expression);
+    fn interpret(&mut self, expression: &Expr) -> Option<i32> {
+        if let Err(err) = self.interpreter.interpreter(expression) {
+            err.print();
+            return Some(70);
+        }
 
         None
     }
 
-    fn run_file(&self, path: &String) {
+    fn run_file(&mut self, path: &str) {
+        let source = fs::read_to_string(path).expect("Could not read the file");
+        self.run_source(&source);
+    }
+
+    /// Runs an AST saved by `loxite ast --format=json`
+    fn run_ast_file(&mut self, path: &str) {
         let source = fs::read_to_string(path).expect("Could not read the file");
-        if let Some(error_code) = self.run(&source) {
+        let expression = JsonValue::parse(&source).and_then(|json| Expr::from_json(&json));
+        let error_code = match expression {
+            Ok(expression) => self.interpret(&expression),
+            Err(err) => {
+                eprintln!("{}", err);
+                Some(65)
+            }
+        };
+        if let Some(error_code) = error_code {
             exit(error_code);
         }
     }
 
-    fn run_prompt(&self) {
+    /// Runs the whole program read from stdin, e.g. `echo '1 + 2' | loxite -`
+    fn run_stdin(&mut self) {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .expect("Could not read from stdin");
+        self.run_source(&source);
+    }
+
+    fn run_source(&mut self, source: &str) {
+        if let Some(error_code) = self.run(source) {
+            exit(error_code);
+        }
+    }
+
+    fn run_prompt(&mut self) {
         loop {
             print!(">> ");
             io::stdout().flush().unwrap();
@@ -65,21 +109,153 @@ impl Loxite {
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// `loxite fmt [--check] <file>...` rewrites each file in its canonical layout.
+/// With `--check` nothing is written and the exit code is 1 if any file would change.
+fn run_fmt(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if paths.is_empty() {
+        println!("Usage: loxite fmt [--check] <file>...");
+        exit(64);
+    }
+
+    let mut unformatted = false;
+    for path in paths {
+        let source = fs::read_to_string(path).expect("Could not read the file");
+        let Some(formatted) = Formatter.format(&source) else {
+            exit(65);
+        };
+        if formatted == source {
+            continue;
+        }
 
-    println!("{:?}", args);
+        if check {
+            eprintln!("{} is not formatted", path);
+            unformatted = true;
+        } else {
+            fs::write(path, formatted).expect("Could not write the file");
+        }
+    }
 
-    if args.len() > 2 {
-        println!("Usage: loxite [script]");
+    if unformatted {
+        exit(1);
+    }
+}
+
+/// `loxite ast [--format=<format>] <file>` prints the parsed expression tree
+fn run_ast(args: &[String]) {
+    fn usage() -> ! {
+        println!("Usage: loxite ast [--format=sexpr|rpn|infix|dot|json] <file>");
+        exit(64);
+    }
+
+    let mut format = "sexpr";
+    let mut path = None;
+    for arg in args {
+        match arg.strip_prefix("--format=") {
+            Some(value) => format = value,
+            None if path.is_none() => path = Some(arg),
+            None => usage(),
+        }
+    }
+    let Some(path) = path else {
+        usage();
+    };
+
+    let printer: Box<dyn ExprPrinter> = match format {
+        "sexpr" => Box::new(AstPrinter),
+        "rpn" => Box::new(RpnPrinter),
+        "infix" => Box::new(InfixPrinter),
+        "dot" => Box::new(DotPrinter),
+        "json" => Box::new(JsonPrinter),
+        _ => usage(),
+    };
+
+    let source = fs::read_to_string(path).expect("Could not read the file");
+    let mut lexer = Lexer::new(&source);
+    let tokens = lexer.scan_tokens();
+    if lexer.had_error {
+        exit(65);
+    }
+    let Some(expression) = Parser::new(tokens).parser() else {
+        exit(65);
+    };
+
+    println!("{}", printer.print(&expression).trim_end());
+}
+
+/// `loxite highlight [--format=<format>] <file>` prints the file with its
+/// tokens coloured, for the terminal or as HTML
+fn run_highlight(args: &[String]) {
+    fn usage() -> ! {
+        println!("Usage: loxite highlight [--format=ansi|html] <file>");
         exit(64);
     }
 
-    let loxite = Loxite::new();
+    let mut format = "ansi";
+    let mut path = None;
+    for arg in args {
+        match arg.strip_prefix("--format=") {
+            Some(value) => format = value,
+            None if path.is_none() => path = Some(arg),
+            None => usage(),
+        }
+    }
+    let Some(path) = path else {
+        usage();
+    };
+
+    let source = fs::read_to_string(path).expect("Could not read the file");
+    let output = match format {
+        "ansi" => to_ansi(&source),
+        "html" => to_html(&source),
+        _ => usage(),
+    };
+    // Highlighting is quiet, so the errors are reported by lexing again
+    Lexer::new(&source).scan_tokens();
+    print!("{}", output);
+}
+
+/// `loxite lsp` serves the Language Server Protocol over stdin and stdout
+fn run_lsp() {
+    let code = LanguageServer::new()
+        .serve(io::stdin().lock(), io::stdout().lock())
+        .expect("Could not talk to the client");
+    exit(code);
+}
 
-    if args.len() == 2 {
-        loxite.run_file(&args[1]);
-    } else {
-        loxite.run_prompt();
+/// `loxite dap` serves the Debug Adapter Protocol over stdin and stdout
+fn run_dap() {
+    DebugAdapter::new(io::stdin().lock(), io::stdout())
+        .serve()
+        .expect("Could not talk to the client");
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    match args.as_slice() {
+        [_] => Loxite::new().run_prompt(),
+        [_, command, rest @ ..] if command == "fmt" => run_fmt(rest),
+        [_, command, rest @ ..] if command == "ast" => run_ast(rest),
+        [_, command, rest @ ..] if command == "highlight" => run_highlight(rest),
+        [_, command] if command == "lsp" => run_lsp(),
+        [_, command] if command == "dap" => run_dap(),
+        // Lox has no calls, so a script could not read any arguments: extra
+        // ones are rejected rather than dropped
+        [_, command, path] if command == "run-ast" => Loxite::new().run_ast_file(path),
+        [_, flag, source] if flag == "-e" => Loxite::new().run_source(source),
+        [_, path] if path == "-" => Loxite::new().run_stdin(),
+        [_, path] if path != "-e" => Loxite::new().run_file(path),
+        _ => {
+            println!("Usage: loxite [script | -e <source> | -]");
+            println!("       loxite fmt [--check] <file>...");
+            println!("       loxite ast [--format=sexpr|rpn|infix|dot|json] <file>");
+            println!("       loxite run-ast <file.json>");
+            println!("       loxite highlight [--format=ansi|html] <file>");
+            println!("       loxite lsp");
+            println!("       loxite dap");
+            exit(64);
+        }
     }
 }
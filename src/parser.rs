@@ -1,115 +1,231 @@
+use std::mem;
+
 use crate::{
     error::{LoxiteError, ParserError},
-    expr::{BinaryExpr, Expr, GroupingExpr, LiteralExpr, UnaryExpr},
-    token::{Token, TokenLiteral, TokenType},
+    expr::{
+        BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+    },
+    token::{Span, Token, TokenLiteral, TokenType},
 };
 
 // --------------------- GRAMMAR -------------------------------
-// expression     → equality ;
-// equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-// comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-// term           → factor ( ( "-" | "+" ) factor )* ;
-// factor         → unary ( ( "/" | "*" ) unary )* ;
+// expression     → comma ;
+// comma          → conditional ( "," conditional )* ;
+// conditional    → binary ( "?" expression ":" conditional )? ;
+// binary         → unary ( BINARY_OPERATOR unary )* ;
 // unary          → ( "!" | "-" ) unary
 //                | primary ;
 // primary        → NUMBER | STRING | "true" | "false" | "nil"
-//                | "(" expression ")" ;
+//                | "(" expression ")"
+//                | INTERPOLATION expression ( INTERPOLATION expression )* STRING
+//                | BINARY_OPERATOR binary ;     (error: missing left operand)
+//
+// comma, conditional and binary are all parsed by `infix`, which reads the
+// precedence and associativity of each operator from INFIX_OPERATORS.
 // --------------------------------------------------------------
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+/// How tightly an operator binds, loosest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Precedence {
+    /// Lowest, so that argument lists can parse their elements at
+    /// `Conditional` and keep the comma as a separator
+    Comma,
+    Conditional,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
-    }
-
-    pub fn parser(&mut self) -> Option<Expr> {
-        match self.expression() {
-            Ok(expr) => Some(expr),
-            Err(err) => {
-                err.print();
-                None
-            }
+impl Precedence {
+    /// The next tighter level
+    pub(crate) fn next(self) -> Precedence {
+        match self {
+            Precedence::Comma => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor | Precedence::Unary => Precedence::Unary,
         }
     }
+}
 
-    fn expression(&mut self) -> Result<Expr, LoxiteError> {
-        self.equality()
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Associativity {
+    Left,
+    Right,
+}
 
-    fn equality(&mut self) -> Result<Expr, LoxiteError> {
-        let mut expr = self.comparison()?;
+/// Every operator that follows a left operand. `?` starts a conditional; the
+/// others build a `BinaryExpr`.
+const INFIX_OPERATORS: &[(TokenType, Precedence, Associativity)] = &[
+    (TokenType::Comma, Precedence::Comma, Associativity::Left),
+    (
+        TokenType::Question,
+        Precedence::Conditional,
+        Associativity::Right,
+    ),
+    (
+        TokenType::BangEqual,
+        Precedence::Equality,
+        Associativity::Left,
+    ),
+    (
+        TokenType::EqualEqual,
+        Precedence::Equality,
+        Associativity::Left,
+    ),
+    (
+        TokenType::Greater,
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    (
+        TokenType::GreaterEqual,
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    (TokenType::Less, Precedence::Comparison, Associativity::Left),
+    (
+        TokenType::LessEqual,
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    (TokenType::Minus, Precedence::Term, Associativity::Left),
+    (TokenType::Plus, Precedence::Term, Associativity::Left),
+    (TokenType::Slash, Precedence::Factor, Associativity::Left),
+    (TokenType::Star, Precedence::Factor, Associativity::Left),
+];
+
+pub(crate) fn infix_operator(token_type: TokenType) -> Option<(Precedence, Associativity)> {
+    INFIX_OPERATORS
+        .iter()
+        .find(|(operator, _, _)| *operator == token_type)
+        .map(|(_, precedence, associativity)| (*precedence, *associativity))
+}
 
-        while self.match_token(&[TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous().clone();
-            let right = self.comparison()?;
-            expr = Expr::Binary(Box::new(BinaryExpr {
-                left: expr,
-                operator,
-                right,
-            }));
-        }
+/// Parses a stream of tokens ending with `EOF`, pulling them one at a time
+pub struct Parser<'a, I: Iterator<Item = Token<'a>>> {
+    tokens: I,
+    /// The next token to parse, returned by `peek`
+    current: Token<'a>,
+    previous: Token<'a>,
+    had_error: bool,
+    errors: Vec<ParserError>,
+    /// Set to collect errors without printing them
+    quiet: bool,
+}
 
-        Ok(expr)
+impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
+    /// Takes the tokens from `Lexer::scan_tokens`, or a `&mut Lexer` to scan
+    /// them as the parser needs them. In that case lexer and parser errors
+    /// are reported in the order they are found.
+    pub fn new(tokens: impl IntoIterator<IntoIter = I>) -> Self {
+        let mut tokens = tokens.into_iter();
+        let current = tokens.next().expect("the token stream ends with EOF");
+        Parser {
+            tokens,
+            previous: current.clone(),
+            current,
+            had_error: false,
+            errors: vec![],
+            quiet: false,
+        }
     }
 
-    fn comparison(&mut self) -> Result<Expr, LoxiteError> {
-        let mut expr = self.term()?;
-
-        while self.match_token(&[
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Expr::Binary(Box::new(BinaryExpr {
-                left: expr,
-                operator,
-                right,
-            }))
+    /// Collects errors in `errors` without printing them, for tools that
+    /// report them their own way
+    pub fn quiet(self) -> Self {
+        Parser {
+            quiet: true,
+            ..self
         }
-
-        Ok(expr)
     }
 
-    fn term(&mut self) -> Result<Expr, LoxiteError> {
-        let mut expr = self.factor()?;
-
-        while self.match_token(&[TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Expr::Binary(Box::new(BinaryExpr {
-                left: expr,
-                operator,
-                right,
-            }))
+    /// Parses the whole expression, which must use up every token. Errors are
+    /// reported as they are found, and `None` is returned if there was any.
+    pub fn parser(&mut self) -> Option<Expr<'a>> {
+        let result = self.expression().and_then(|expr| {
+            if !self.is_at_end() {
+                return Err(LoxiteError::Parser(ParserError {
+                    token: self.peek().clone().into_owned(),
+                    message: "Expected end of expression.".to_string(),
+                }));
+            }
+            Ok(expr)
+        });
+        match result {
+            Ok(expr) if !self.had_error => Some(expr),
+            Ok(_) => None,
+            Err(err) => {
+                if !self.quiet {
+                    err.print();
+                }
+                if let LoxiteError::Parser(error) = err {
+                    self.errors.push(error);
+                }
+                None
+            }
         }
+    }
 
-        Ok(expr)
+    /// The errors reported so far, in source order
+    pub fn errors(&self) -> &[ParserError] {
+        &self.errors
     }
 
-    fn factor(&mut self) -> Result<Expr, LoxiteError> {
+    fn expression(&mut self) -> Result<Expr<'a>, LoxiteError> {
+        self.infix(Precedence::Comma)
+    }
+
+    /// Precedence climbing: parses a unary operand, then keeps folding in
+    /// infix operators that bind at least as tightly as `min_precedence`.
+    /// A left-associative operator parses its right operand one level
+    /// tighter, so `1 - 2 - 3` groups as `(1 - 2) - 3`, while a
+    /// right-associative one stays on its own level, so `a ? b : c ? d : e`
+    /// groups as `a ? b : (c ? d : e)`.
+    fn infix(&mut self, min_precedence: Precedence) -> Result<Expr<'a>, LoxiteError> {
         let mut expr = self.unary()?;
 
-        while self.match_token(&[TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous().clone();
-            let right = self.unary()?;
-            expr = Expr::Binary(Box::new(BinaryExpr {
-                left: expr,
-                operator,
-                right,
-            }))
+        while let Some((precedence, associativity)) = infix_operator(self.peek().token_type) {
+            if precedence < min_precedence {
+                break;
+            }
+
+            let operator = self.advance().clone();
+            let right_precedence = match associativity {
+                Associativity::Left => precedence.next(),
+                Associativity::Right => precedence,
+            };
+
+            if operator.token_type == TokenType::Question {
+                let then_branch = self.expression()?;
+                self.consume(
+                    TokenType::Colon,
+                    "Expected ':' after then branch of conditional expression.",
+                )?;
+                let else_branch = self.infix(right_precedence)?;
+                expr = Expr::Conditional(Box::new(ConditionalExpr {
+                    condition: expr,
+                    then_branch,
+                    else_branch,
+                }));
+            } else {
+                let right = self.infix(right_precedence)?;
+                expr = Expr::Binary(Box::new(BinaryExpr {
+                    left: expr,
+                    operator,
+                    right,
+                }));
+            }
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, LoxiteError> {
+    fn unary(&mut self) -> Result<Expr<'a>, LoxiteError> {
         if self.match_token(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous().clone();
             let right = self.unary()?;
@@ -118,46 +234,137 @@ impl Parser {
         self.primary()
     }
 
-    fn primary(&mut self) -> Result<Expr, LoxiteError> {
+    fn primary(&mut self) -> Result<Expr<'a>, LoxiteError> {
         if self.match_token(&[TokenType::False]) {
             return Ok(Expr::Literal(LiteralExpr {
                 value: TokenLiteral::Boolean(false),
+                lexeme: None,
+                span: self.previous().span,
             }));
         }
         if self.match_token(&[TokenType::True]) {
             return Ok(Expr::Literal(LiteralExpr {
                 value: TokenLiteral::Boolean(true),
+                lexeme: None,
+                span: self.previous().span,
             }));
         }
         if self.match_token(&[TokenType::Nil]) {
             return Ok(Expr::Literal(LiteralExpr {
                 value: TokenLiteral::Empty,
+                lexeme: None,
+                span: self.previous().span,
             }));
         }
 
-        if self.match_token(&[TokenType::Number, TokenType::String]) {
+        if self.match_token(&[TokenType::Number]) {
+            return Ok(Expr::Literal(LiteralExpr {
+                value: self.previous().literal.clone(),
+                lexeme: Some(self.previous().lexeme.to_string()),
+                span: self.previous().span,
+            }));
+        }
+        if self.match_token(&[TokenType::String]) {
             return Ok(Expr::Literal(LiteralExpr {
                 value: self.previous().literal.clone(),
+                lexeme: None,
+                span: self.previous().span,
             }));
         }
 
+        if self.match_token(&[TokenType::Interpolation]) {
+            return self.interpolation();
+        }
+
         if self.match_token(&[TokenType::LeftParen]) {
+            let start = self.previous().span.start;
             let expr = self.expression()?;
-            self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-            return Ok(Expr::Grouping(Box::new(GroupingExpr { expression: expr })));
+            let end = self
+                .consume(TokenType::RightParen, "Expected ')' after expression")?
+                .span
+                .end;
+            return Ok(Expr::Grouping(Box::new(GroupingExpr {
+                expression: expr,
+                span: Span { start, end },
+            })));
+        }
+
+        // Error production for a binary operator without a left operand, like
+        // `* 3`. The right operand is parsed and returned in place of the
+        // whole expression so parsing can carry on and report later errors.
+        let token_type = self.peek().token_type;
+        if let Some((precedence, _)) = infix_operator(token_type) {
+            if token_type != TokenType::Question {
+                let operator = self.advance().clone();
+                self.error(&operator, "Missing left operand.");
+                return self.infix(precedence.next());
+            }
         }
 
         // let err = LoxiteError::Parser(ParserError {
-        //     token: self.peek().clone(),
+        //     token: self.peek().clone().into_owned(),
         //     message: "Expected expression.".to_string(),
         // });
         // err.print();
         // return Err(err);
 
-        return Err(LoxiteError::Parser(ParserError {
-            token: self.peek().clone(),
+        Err(LoxiteError::Parser(ParserError {
+            token: self.peek().clone().into_owned(),
             message: "Expected expression.".to_string(),
-        }));
+        }))
+    }
+
+    /// Parses the rest of an interpolated string whose first `Interpolation`
+    /// token has just been consumed. The lexer ends each embedded expression
+    /// with the next text segment, so anything else after the expression
+    /// means its `}` is missing.
+    fn interpolation(&mut self) -> Result<Expr<'a>, LoxiteError> {
+        let start = self.previous().span.start;
+        let mut strings = vec![self.previous().literal.to_string()];
+        let mut expressions = vec![];
+
+        loop {
+            // The text after `${}` would otherwise be taken for a string
+            // literal, and the error reported at the end of the string
+            if self.peek().lexeme.starts_with('}') {
+                return Err(LoxiteError::Parser(ParserError {
+                    token: self.previous().clone().into_owned(),
+                    message: "Expected expression in interpolation.".to_string(),
+                }));
+            }
+            expressions.push(self.expression()?);
+            if self.match_token(&[TokenType::Interpolation]) {
+                strings.push(self.previous().literal.to_string());
+                continue;
+            }
+
+            let end = self.consume(
+                TokenType::String,
+                "Expected '}' after interpolated expression.",
+            )?;
+            strings.push(end.literal.to_string());
+            return Ok(Expr::Interpolation(Box::new(InterpolationExpr {
+                strings,
+                expressions,
+                span: Span {
+                    start,
+                    end: end.span.end,
+                },
+            })));
+        }
+    }
+
+    /// Reports an error the parser can recover from without unwinding
+    fn error(&mut self, token: &Token<'a>, message: &str) {
+        let error = ParserError {
+            token: token.clone().into_owned(),
+            message: message.to_string(),
+        };
+        if !self.quiet {
+            LoxiteError::Parser(error.clone()).print();
+        }
+        self.errors.push(error);
+        self.had_error = true;
     }
 
     #[allow(dead_code)]
@@ -185,18 +392,18 @@ impl Parser {
         }
     }
 
-    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, LoxiteError> {
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token<'a>, LoxiteError> {
         if self.check(&token_type) {
             return Ok(self.advance());
         }
         // let err = LoxiteError::Parser(ParserError {
-        //     token: self.peek().clone(),
+        //     token: self.peek().clone().into_owned(),
         //     message: message.to_string(),
         // });
         // err.print();
         // Err(err)
         Err(LoxiteError::Parser(ParserError {
-            token: self.peek().clone(),
+            token: self.peek().clone().into_owned(),
             message: message.to_string(),
         }))
     }
@@ -218,9 +425,13 @@ impl Parser {
         &self.peek().token_type == token_type
     }
 
-    fn advance(&mut self) -> &Token {
+    fn advance(&mut self) -> &Token<'a> {
         if !self.is_at_end() {
-            self.current += 1
+            let next = match self.tokens.next() {
+                Some(token) => token,
+                None => self.current.clone(),
+            };
+            self.previous = mem::replace(&mut self.current, next);
         }
         self.previous()
     }
@@ -229,11 +440,174 @@ impl Parser {
         self.peek().token_type == TokenType::EOF
     }
 
-    fn peek(&self) -> &Token {
-        self.tokens.get(self.current).unwrap()
+    fn peek(&self) -> &Token<'a> {
+        &self.current
+    }
+
+    fn previous(&self) -> &Token<'a> {
+        &self.previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast_printer::AstPrinter, expr::Expr, lexer::Lexer, parser::Parser};
+
+    fn parse(source: &str) -> String {
+        let tokens = Lexer::new(source).scan_tokens();
+        let expr = Parser::new(tokens).parser().unwrap();
+        AstPrinter.get_expr_as_str(&expr)
+    }
+
+    #[test]
+    fn test_binary_operators_are_left_associative() {
+        for operator in [",", "!=", "==", ">", ">=", "<", "<=", "-", "+", "/", "*"] {
+            let source = format!("1 {0} 2 {0} 3", operator);
+            let expected = format!("({0} ({0} 1 2) 3)", operator);
+            assert_eq!(parse(&source), expected);
+        }
+    }
+
+    #[test]
+    fn test_mixed_operators_on_the_same_level() {
+        assert_eq!(parse("10 - 2 + 3"), "(+ (- 10 2) 3)");
+        assert_eq!(parse("8 / 2 * 4"), "(* (/ 8 2) 4)");
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(parse("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(parse("1 * 2 + 3"), "(+ (* 1 2) 3)");
+        assert_eq!(parse("1 < 2 == 3 > 4"), "(== (< 1 2) (> 3 4))");
+        assert_eq!(
+            parse("-1 - -2 * (3 + 4)"),
+            "(- (- 1) (* (- 2) (group (+ 3 4))))"
+        );
+        assert_eq!(parse("!true == false"), "(== (! true) false)");
+    }
+
+    #[test]
+    fn test_conditional() {
+        assert_eq!(parse("1 ? 2 : 3 ? 4 : 5"), "(?: 1 2 (?: 3 4 5))");
+        assert_eq!(parse("1 ? 2 ? 3 : 4 : 5"), "(?: 1 (?: 2 3 4) 5)");
+        assert_eq!(
+            parse("1 == 2 ? 3 + 4 : 5 * 6"),
+            "(?: (== 1 2) (+ 3 4) (* 5 6))"
+        );
+        assert_eq!(parse("(1 ? 2 : 3) ? 4 : 5"), "(?: (group (?: 1 2 3)) 4 5)");
+    }
+
+    #[test]
+    fn test_comma() {
+        assert_eq!(parse("1, 2 + 3"), "(, 1 (+ 2 3))");
+        assert_eq!(parse("1 ? 2 : 3, 4"), "(, (?: 1 2 3) 4)");
+        assert_eq!(parse("1 ? 2, 3 : 4"), "(?: 1 (, 2 3) 4)");
+        assert_eq!(parse("(1, 2) == 3"), "(== (group (, 1 2)) 3)");
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq!(
+            parse("\"a ${1 + 2} b ${\"c ${3}\"}\""),
+            "(interpolate \"a \" (+ 1 2) \" b \" (interpolate \"c \" 3 \"\") \"\")"
+        );
+        assert_eq!(
+            parse("\"${1, 2}\" + \"\""),
+            "(+ (interpolate \"\" (, 1 2) \"\") \"\")"
+        );
+
+        for source in ["\"${1 2}\"", "\"${}\"", "\"${1"] {
+            let tokens = Lexer::new(source).scan_tokens();
+            assert!(Parser::new(tokens).parser().is_none(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_empty_interpolation() {
+        for (source, lexeme) in [("\"${}\"", "\"${"), ("\"a ${1} b ${}\"", "} b ${")] {
+            let tokens = Lexer::new(source).scan_tokens();
+            let mut parser = Parser::new(tokens).quiet();
+            assert!(parser.parser().is_none(), "{}", source);
+            let error = &parser.errors()[0];
+            assert_eq!(error.message, "Expected expression in interpolation.");
+            assert_eq!(error.token.lexeme, lexeme, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_pulls_tokens_from_lexer() {
+        let mut lexer = Lexer::new("(1 + 2) * 3");
+        let expr = Parser::new(&mut lexer).parser().unwrap();
+        assert_eq!(AstPrinter.get_expr_as_str(&expr), "(* (group (+ 1 2)) 3)");
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_missing_left_operand() {
+        for source in ["* 3", "== 1", ", 2", "1 + (/ 2)", "* 1 == / 2"] {
+            let tokens = Lexer::new(source).scan_tokens();
+            let mut parser = Parser::new(tokens);
+            assert!(parser.parser().is_none(), "{}", source);
+            assert!(parser.is_at_end(), "{} was not parsed to the end", source);
+        }
+    }
+
+    #[test]
+    fn test_spans_cover_each_node() {
+        let source = "(1) ? \"a ${-2}\" : true == nil";
+        let tokens = Lexer::new(source).scan_tokens();
+        let Some(Expr::Conditional(conditional)) = Parser::new(tokens).parser() else {
+            panic!("Expected a conditional");
+        };
+        let text = |expr: &Expr| {
+            let span = expr.span();
+            &source[span.start..span.end]
+        };
+        assert_eq!(text(&conditional.condition), "(1)");
+        assert_eq!(text(&conditional.then_branch), "\"a ${-2}\"");
+        assert_eq!(text(&conditional.else_branch), "true == nil");
+    }
+
+    #[test]
+    fn test_trailing_tokens_are_an_error() {
+        for source in ["1 2 + 3", "1 + 2 // a\n/* b */ 3", "(1) )"] {
+            let tokens = Lexer::new(source).scan_tokens();
+            let mut parser = Parser::new(tokens);
+            assert!(parser.parser().is_none(), "{}", source);
+            assert_eq!(parser.errors()[0].message, "Expected end of expression.");
+        }
+    }
+
+    #[test]
+    fn test_collects_errors() {
+        let tokens = Lexer::new("* 1 == / 2").scan_tokens();
+        let mut parser = Parser::new(tokens);
+        parser.parser();
+        let errors: Vec<(&str, &str)> = parser
+            .errors()
+            .iter()
+            .map(|error| (error.token.lexeme.as_ref(), error.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("*", "Missing left operand."),
+                ("/", "Missing left operand.")
+            ]
+        );
+
+        let tokens = Lexer::new("1 +").scan_tokens();
+        let mut parser = Parser::new(tokens);
+        parser.parser();
+        assert_eq!(parser.errors()[0].message, "Expected expression.");
     }
 
-    fn previous(&self) -> &Token {
-        self.tokens.get(self.current - 1).unwrap()
+    #[test]
+    fn test_quiet_mode_still_collects_errors() {
+        let mut lexer = Lexer::new("1 + @").quiet();
+        let mut parser = Parser::new(&mut lexer).quiet();
+        assert!(parser.parser().is_none());
+        assert_eq!(parser.errors()[0].message, "Expected expression.");
+        assert_eq!(lexer.errors()[0].message, "Unexpected character");
     }
 }
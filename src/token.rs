@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{borrow::Cow, fmt, str::FromStr};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
@@ -14,6 +14,8 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
 
     // one or two char tokens
     Bang,
@@ -27,6 +29,10 @@ pub enum TokenType {
 
     // literals
     String,
+    /// The part of a string before a `${`, including the `}` closing the
+    /// previous embedded expression if there was one. The string ends with
+    /// a `String` token.
+    Interpolation,
     Number,
     Identifier,
 
@@ -51,15 +57,91 @@ pub enum TokenType {
     EOF,
 }
 
-#[derive(Debug, Clone)]
-pub struct Token {
+impl FromStr for TokenType {
+    type Err = String;
+
+    /// Parses the variant name, as printed by `Debug`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "LeftParen" => Ok(TokenType::LeftParen),
+            "RightParen" => Ok(TokenType::RightParen),
+            "LeftBrace" => Ok(TokenType::LeftBrace),
+            "RightBrace" => Ok(TokenType::RightBrace),
+            "Comma" => Ok(TokenType::Comma),
+            "Dot" => Ok(TokenType::Dot),
+            "Minus" => Ok(TokenType::Minus),
+            "Plus" => Ok(TokenType::Plus),
+            "Semicolon" => Ok(TokenType::Semicolon),
+            "Slash" => Ok(TokenType::Slash),
+            "Star" => Ok(TokenType::Star),
+            "Question" => Ok(TokenType::Question),
+            "Colon" => Ok(TokenType::Colon),
+            "Bang" => Ok(TokenType::Bang),
+            "BangEqual" => Ok(TokenType::BangEqual),
+            "Equal" => Ok(TokenType::Equal),
+            "EqualEqual" => Ok(TokenType::EqualEqual),
+            "Greater" => Ok(TokenType::Greater),
+            "GreaterEqual" => Ok(TokenType::GreaterEqual),
+            "Less" => Ok(TokenType::Less),
+            "LessEqual" => Ok(TokenType::LessEqual),
+            "String" => Ok(TokenType::String),
+            "Interpolation" => Ok(TokenType::Interpolation),
+            "Number" => Ok(TokenType::Number),
+            "Identifier" => Ok(TokenType::Identifier),
+            "And" => Ok(TokenType::And),
+            "Class" => Ok(TokenType::Class),
+            "Else" => Ok(TokenType::Else),
+            "False" => Ok(TokenType::False),
+            "Fun" => Ok(TokenType::Fun),
+            "For" => Ok(TokenType::For),
+            "If" => Ok(TokenType::If),
+            "Nil" => Ok(TokenType::Nil),
+            "Or" => Ok(TokenType::Or),
+            "Print" => Ok(TokenType::Print),
+            "Return" => Ok(TokenType::Return),
+            "Super" => Ok(TokenType::Super),
+            "This" => Ok(TokenType::This),
+            "True" => Ok(TokenType::True),
+            "Var" => Ok(TokenType::Var),
+            "While" => Ok(TokenType::While),
+            "EOF" => Ok(TokenType::EOF),
+            _ => Err(format!("Unknown token type '{}'", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub lexeme: String,
+    /// Borrowed from the source for tokens coming from the lexer
+    pub lexeme: Cow<'a, str>,
     pub literal: TokenLiteral,
     pub line: usize,
+    pub span: Span,
 }
 
-impl fmt::Display for Token {
+impl Token<'_> {
+    /// Copies the lexeme if it is borrowed, so the token can outlive the
+    /// source, e.g. in an error
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            token_type: self.token_type,
+            lexeme: Cow::Owned(self.lexeme.into_owned()),
+            literal: self.literal,
+            line: self.line,
+            span: self.span,
+        }
+    }
+}
+
+/// Where a lexeme sits in the source, as byte offsets (`end` is exclusive)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -69,7 +151,7 @@ impl fmt::Display for Token {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenLiteral {
     String(String),
     Number(f64),
@@ -77,6 +159,55 @@ pub enum TokenLiteral {
     Empty,
 }
 
+impl TokenLiteral {
+    /// The literal as it would be written in Lox source, with strings quoted
+    /// and escaped. Numbers use an exponent when it is shorter; infinities,
+    /// which overflow any literal, are written as one that overflows, and NaN
+    /// as the division that gives it.
+    pub fn to_source(&self) -> String {
+        match self {
+            TokenLiteral::String(value) => format!("\"{}\"", escape_string(value)),
+            TokenLiteral::Number(value) if value.is_nan() => "(0 / 0)".to_string(),
+            TokenLiteral::Number(value) if value.is_infinite() => if value.is_sign_positive() {
+                "1e999"
+            } else {
+                "-1e999"
+            }
+            .to_string(),
+            TokenLiteral::Number(value) => {
+                let (plain, exponent) = (value.to_string(), format!("{:e}", value));
+                if exponent.len() < plain.len() {
+                    exponent
+                } else {
+                    plain
+                }
+            }
+            TokenLiteral::Empty => "nil".to_string(),
+            literal => literal.to_string(),
+        }
+    }
+}
+
+/// Escapes `value` so it can be written between the quotes of a Lox string
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{{{:X}}}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
 impl fmt::Display for TokenLiteral {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -87,3 +218,67 @@ impl fmt::Display for TokenLiteral {
         }
     }
 }
+
+/// What a piece of trivia is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs and carriage returns
+    Whitespace,
+    /// A single `\n`
+    Newline,
+    LineComment,
+    BlockComment,
+    /// The `#!` line at the very start of a script
+    Shebang,
+    /// Text the lexer reported an error for and did not turn into a token
+    Skipped,
+}
+
+/// Source text between two tokens, kept by a lossless scan
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// A token with the trivia around it. Trailing trivia runs up to the end of
+/// the token's line; everything after that leads the next token, so writing
+/// out every token of a source in order gives back the source exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken<'a> {
+    pub leading: Vec<Trivia<'a>>,
+    pub token: Token<'a>,
+    pub trailing: Vec<Trivia<'a>>,
+}
+
+impl fmt::Display for LosslessToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading {
+            write!(f, "{}", trivia.text)?;
+        }
+        write!(f, "{}", self.token.lexeme)?;
+        for trivia in &self.trailing {
+            write!(f, "{}", trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// A `//` or `/* */` comment skipped by the lexer, kept so tools like the
+/// formatter can put it back into the source.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub text: String,
+    /// The line the comment starts on
+    pub line: usize,
+    pub span: Span,
+}
+
+impl Comment {
+    /// The line the comment ends on, later than `line` for block comments
+    /// spanning several lines
+    pub fn end_line(&self) -> usize {
+        self.line + self.text.matches('\n').count()
+    }
+}
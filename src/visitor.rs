@@ -0,0 +1,351 @@
+use crate::expr::{
+    BinaryExpr, ConditionalExpr, Expr, GroupingExpr, InterpolationExpr, LiteralExpr, UnaryExpr,
+};
+
+/// What a visitor returns, so that the default `visit_*` methods can give a
+/// result for a node from the results of its children.
+pub trait VisitResult: Sized {
+    /// The result of a node without children
+    fn output() -> Self;
+
+    /// The result of a node from those of its children, in source order. By
+    /// default the last one, or `output()` if there are none.
+    fn join(results: impl IntoIterator<Item = Self>) -> Self {
+        results.into_iter().last().unwrap_or_else(Self::output)
+    }
+}
+
+impl VisitResult for () {
+    fn output() {}
+}
+
+/// Counts, added up over the children
+impl VisitResult for usize {
+    fn output() -> usize {
+        0
+    }
+
+    fn join(results: impl IntoIterator<Item = usize>) -> usize {
+        results.into_iter().sum()
+    }
+}
+
+/// Text, concatenated over the children
+impl VisitResult for String {
+    fn output() -> String {
+        String::new()
+    }
+
+    fn join(results: impl IntoIterator<Item = String>) -> String {
+        results.into_iter().collect()
+    }
+}
+
+/// Stops at the first error
+impl<T: VisitResult, E> VisitResult for Result<T, E> {
+    fn output() -> Self {
+        Ok(T::output())
+    }
+
+    fn join(results: impl IntoIterator<Item = Self>) -> Self {
+        results
+            .into_iter()
+            .collect::<Result<Vec<T>, E>>()
+            .map(T::join)
+    }
+}
+
+/// Operation over an expression tree, with one method per `Expr` variant.
+///
+/// `visit_expr` dispatches on the variant. Every other method visits the
+/// children of its node with the matching `walk_*` helper by default, so
+/// implementations only override the variants they care about.
+pub trait Visitor<R: VisitResult> {
+    fn visit_expr(&mut self, expr: &Expr) -> R {
+        match expr {
+            Expr::Unary(expr) => self.visit_unary(expr),
+            Expr::Binary(expr) => self.visit_binary(expr),
+            Expr::Grouping(expr) => self.visit_grouping(expr),
+            Expr::Conditional(expr) => self.visit_conditional(expr),
+            Expr::Interpolation(expr) => self.visit_interpolation(expr),
+            Expr::Literal(expr) => self.visit_literal(expr),
+        }
+    }
+
+    fn visit_unary(&mut self, expr: &UnaryExpr) -> R {
+        walk_unary(self, expr)
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryExpr) -> R {
+        R::join(walk_binary(self, expr))
+    }
+
+    fn visit_grouping(&mut self, expr: &GroupingExpr) -> R {
+        walk_grouping(self, expr)
+    }
+
+    fn visit_conditional(&mut self, expr: &ConditionalExpr) -> R {
+        R::join(walk_conditional(self, expr))
+    }
+
+    fn visit_interpolation(&mut self, expr: &InterpolationExpr) -> R {
+        R::join(walk_interpolation(self, expr))
+    }
+
+    fn visit_literal(&mut self, _expr: &LiteralExpr) -> R {
+        R::output()
+    }
+}
+
+/// Like `Visitor`, but with mutable access to the nodes so transforms can
+/// rewrite the tree in place, including replacing a whole node in `visit_expr_mut`.
+pub trait VisitorMut<R: VisitResult> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) -> R {
+        match expr {
+            Expr::Unary(expr) => self.visit_unary_mut(expr),
+            Expr::Binary(expr) => self.visit_binary_mut(expr),
+            Expr::Grouping(expr) => self.visit_grouping_mut(expr),
+            Expr::Conditional(expr) => self.visit_conditional_mut(expr),
+            Expr::Interpolation(expr) => self.visit_interpolation_mut(expr),
+            Expr::Literal(expr) => self.visit_literal_mut(expr),
+        }
+    }
+
+    fn visit_unary_mut(&mut self, expr: &mut UnaryExpr) -> R {
+        walk_unary_mut(self, expr)
+    }
+
+    fn visit_binary_mut(&mut self, expr: &mut BinaryExpr) -> R {
+        R::join(walk_binary_mut(self, expr))
+    }
+
+    fn visit_grouping_mut(&mut self, expr: &mut GroupingExpr) -> R {
+        walk_grouping_mut(self, expr)
+    }
+
+    fn visit_conditional_mut(&mut self, expr: &mut ConditionalExpr) -> R {
+        R::join(walk_conditional_mut(self, expr))
+    }
+
+    fn visit_interpolation_mut(&mut self, expr: &mut InterpolationExpr) -> R {
+        R::join(walk_interpolation_mut(self, expr))
+    }
+
+    fn visit_literal_mut(&mut self, _expr: &mut LiteralExpr) -> R {
+        R::output()
+    }
+}
+
+impl Expr<'_> {
+    pub fn accept<R: VisitResult>(&self, visitor: &mut impl Visitor<R>) -> R {
+        visitor.visit_expr(self)
+    }
+
+    pub fn accept_mut<R: VisitResult>(&mut self, visitor: &mut impl VisitorMut<R>) -> R {
+        visitor.visit_expr_mut(self)
+    }
+}
+
+/// Visits the operand of a unary expression
+pub fn walk_unary<R: VisitResult, V: Visitor<R> + ?Sized>(visitor: &mut V, expr: &UnaryExpr) -> R {
+    visitor.visit_expr(&expr.right)
+}
+
+/// Visits both operands of a binary expression, left first
+pub fn walk_binary<R: VisitResult, V: Visitor<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &BinaryExpr,
+) -> [R; 2] {
+    [
+        visitor.visit_expr(&expr.left),
+        visitor.visit_expr(&expr.right),
+    ]
+}
+
+/// Visits the expression inside the parentheses
+pub fn walk_grouping<R: VisitResult, V: Visitor<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &GroupingExpr,
+) -> R {
+    visitor.visit_expr(&expr.expression)
+}
+
+/// Visits the condition and both branches, in source order
+pub fn walk_conditional<R: VisitResult, V: Visitor<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &ConditionalExpr,
+) -> [R; 3] {
+    [
+        visitor.visit_expr(&expr.condition),
+        visitor.visit_expr(&expr.then_branch),
+        visitor.visit_expr(&expr.else_branch),
+    ]
+}
+
+/// Visits the embedded expressions of an interpolated string, in source order
+pub fn walk_interpolation<R: VisitResult, V: Visitor<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &InterpolationExpr,
+) -> Vec<R> {
+    expr.expressions
+        .iter()
+        .map(|expression| visitor.visit_expr(expression))
+        .collect()
+}
+
+/// Visits the operand of a unary expression
+pub fn walk_unary_mut<R: VisitResult, V: VisitorMut<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut UnaryExpr,
+) -> R {
+    visitor.visit_expr_mut(&mut expr.right)
+}
+
+/// Visits both operands of a binary expression, left first
+pub fn walk_binary_mut<R: VisitResult, V: VisitorMut<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut BinaryExpr,
+) -> [R; 2] {
+    [
+        visitor.visit_expr_mut(&mut expr.left),
+        visitor.visit_expr_mut(&mut expr.right),
+    ]
+}
+
+/// Visits the expression inside the parentheses
+pub fn walk_grouping_mut<R: VisitResult, V: VisitorMut<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut GroupingExpr,
+) -> R {
+    visitor.visit_expr_mut(&mut expr.expression)
+}
+
+/// Visits the condition and both branches, in source order
+pub fn walk_conditional_mut<R: VisitResult, V: VisitorMut<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut ConditionalExpr,
+) -> [R; 3] {
+    [
+        visitor.visit_expr_mut(&mut expr.condition),
+        visitor.visit_expr_mut(&mut expr.then_branch),
+        visitor.visit_expr_mut(&mut expr.else_branch),
+    ]
+}
+
+/// Visits the embedded expressions of an interpolated string, in source order
+pub fn walk_interpolation_mut<R: VisitResult, V: VisitorMut<R> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut InterpolationExpr,
+) -> Vec<R> {
+    expr.expressions
+        .iter_mut()
+        .map(|expression| visitor.visit_expr_mut(expression))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast_printer::{AstPrinter, ExprPrinter},
+        expr::{Expr, LiteralExpr, UnaryExpr},
+        lexer::Lexer,
+        parser::Parser,
+        token::{Span, TokenLiteral, TokenType},
+        visitor::{walk_unary_mut, Visitor, VisitorMut},
+    };
+
+    fn parse(source: &str) -> Expr<'_> {
+        let tokens = Lexer::new(source).scan_tokens();
+        Parser::new(tokens).parser().unwrap()
+    }
+
+    /// Counts the number literals in a tree
+    struct NumberCounter {
+        count: usize,
+    }
+
+    impl Visitor<()> for NumberCounter {
+        fn visit_literal(&mut self, expr: &LiteralExpr) {
+            if let TokenLiteral::Number(_) = expr.value {
+                self.count += 1;
+            }
+        }
+    }
+
+    /// Counts the literals in a tree through the results, which the default
+    /// methods add up
+    struct LiteralCounter;
+
+    impl Visitor<usize> for LiteralCounter {
+        fn visit_literal(&mut self, _expr: &LiteralExpr) -> usize {
+            1
+        }
+    }
+
+    /// Doubles every number literal
+    struct Doubler;
+
+    impl VisitorMut<()> for Doubler {
+        fn visit_literal_mut(&mut self, expr: &mut LiteralExpr) {
+            if let TokenLiteral::Number(value) = expr.value {
+                expr.value = TokenLiteral::Number(value * 2.0);
+                expr.lexeme = None;
+            }
+        }
+    }
+
+    /// Folds a minus applied to a number literal into a negative literal
+    /// where it is the operand of another unary operator. The default methods
+    /// walk down to every unary expression.
+    struct NegationFolder;
+
+    impl VisitorMut<()> for NegationFolder {
+        fn visit_unary_mut(&mut self, expr: &mut UnaryExpr) {
+            walk_unary_mut(self, expr);
+
+            if let Expr::Unary(operand) = &expr.right {
+                if let (TokenType::Minus, Expr::Literal(literal)) =
+                    (operand.operator.token_type, &operand.right)
+                {
+                    if let TokenLiteral::Number(value) = literal.value {
+                        expr.right = Expr::Literal(LiteralExpr {
+                            value: TokenLiteral::Number(-value),
+                            lexeme: None,
+                            span: Span::default(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_analyzer_visits_every_node() {
+        let mut counter = NumberCounter { count: 0 };
+        parse("(1 + 2) * -3 == \"a ${6}\" ? 4 : 5").accept(&mut counter);
+        assert_eq!(counter.count, 6);
+    }
+
+    #[test]
+    fn test_default_methods_walk_children() {
+        let expr = parse("(1 + 2) * -3 == \"a ${nil}\" ? true : \"b\"");
+        assert_eq!(expr.accept(&mut LiteralCounter), 6);
+
+        let mut expr = parse("-(1 + 2) * \"${3}\"");
+        expr.accept_mut(&mut Doubler);
+        assert_eq!(
+            AstPrinter.print(&expr),
+            "(* (- (group (+ 2 4))) (interpolate \"\" 6 \"\"))"
+        );
+    }
+
+    #[test]
+    fn test_transform_rewrites_tree() {
+        let mut expr = parse("!-1 * -(2 + --3)");
+        expr.accept_mut(&mut NegationFolder);
+        assert_eq!(
+            AstPrinter.print(&expr),
+            "(* (! -1) (- (group (+ 2 (- -3)))))"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Helpers shared by the integration tests that run over the `.lox` corpus.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Every `.lox` file under `tests/lox`, or under `$LOX_TEST_DIR` when set, in
+/// sorted order
+pub fn lox_files() -> Vec<PathBuf> {
+    let dir = env::var("LOX_TEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"));
+
+    let mut files = vec![];
+    collect_lox_files(&dir, &mut files);
+    files.sort();
+    assert!(!files.is_empty(), "no .lox files found in {:?}", dir);
+    files
+}
+
+fn collect_lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("Could not read the test directory") {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_lox_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Golden-file tests in the style of the Crafting Interpreters test suite.
+//!
+//! Every `.lox` file under `tests/lox` (or under `$LOX_TEST_DIR`) is run with the
+//! `loxite` binary and checked against the annotations in its comments:
+//!
+//! - `// expect: <value>` a line the program must print to stdout, in order.
+//! - `// expect runtime error: <message>` the program must fail at runtime on
+//!   that line with `message` and exit with 70.
+//! - `// [line N] Error...` or `// Error...` (on the current line) a lexer or
+//!   parser diagnostic that must be reported; the program must exit with 65.
+
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+#[derive(Default)]
+struct Expectations {
+    output: Vec<String>,
+    errors: Vec<String>,
+    runtime_error: Option<String>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Expectations::default();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+
+            if let Some(position) = line.find(EXPECT) {
+                let value = &line[position + EXPECT.len()..];
+                expectations.output.push(value.to_string());
+            } else if let Some(position) = line.find(EXPECT_RUNTIME_ERROR) {
+                let message = &line[position + EXPECT_RUNTIME_ERROR.len()..];
+                expectations.runtime_error = Some(format!("[line {}]: {}", line_number, message));
+            } else if let Some(position) = line.find("// [line ") {
+                expectations.errors.push(line[position + 3..].to_string());
+            } else if let Some(position) = line.find("// Error") {
+                expectations.errors.push(format!(
+                    "[line {}] {}",
+                    line_number,
+                    &line[position + 3..]
+                ));
+            }
+        }
+
+        expectations
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+fn run_test(path: &Path) -> Vec<String> {
+    let source = fs::read_to_string(path).expect("Could not read the test file");
+    let expectations = Expectations::parse(&source);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_loxite"))
+        .arg(path)
+        .output()
+        .expect("Could not run loxite");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut failures = vec![];
+
+    let actual_output: Vec<&str> = stdout.lines().collect();
+    if actual_output != expectations.output {
+        failures.push(format!(
+            "expected output {:?}, got {:?}",
+            expectations.output, actual_output
+        ));
+    }
+
+    let mut actual_errors = vec![];
+    let mut actual_runtime_error = None;
+    for line in stderr.lines() {
+        if let Some(error) = line
+            .strip_prefix("Lexer error: ")
+            .or_else(|| line.strip_prefix("Parser error: "))
+        {
+            actual_errors.push(error.to_string());
+        } else if let Some(error) = line.strip_prefix("Runtime Error: ") {
+            actual_runtime_error = Some(error.to_string());
+        } else {
+            failures.push(format!("unexpected diagnostic {:?}", line));
+        }
+    }
+
+    if actual_errors != expectations.errors {
+        failures.push(format!(
+            "expected errors {:?}, got {:?}",
+            expectations.errors, actual_errors
+        ));
+    }
+    if actual_runtime_error != expectations.runtime_error {
+        failures.push(format!(
+            "expected runtime error {:?}, got {:?}",
+            expectations.runtime_error, actual_runtime_error
+        ));
+    }
+    if output.status.code() != Some(expectations.exit_code()) {
+        failures.push(format!(
+            "expected exit code {}, got {:?}",
+            expectations.exit_code(),
+            output.status.code()
+        ));
+    }
+
+    failures
+}
+
+#[test]
+fn test_lox_files() {
+    let files = common::lox_files();
+
+    let mut report = String::new();
+    for file in &files {
+        for failure in run_test(file) {
+            report.push_str(&format!("{}: {}\n", file.display(), failure));
+        }
+    }
+
+    assert!(report.is_empty(), "conformance failures:\n{}", report);
+}
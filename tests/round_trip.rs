@@ -0,0 +1,28 @@
+//! Parses every `.lox` file under `tests/lox` (or under `$LOX_TEST_DIR`) into
+//! a concrete syntax tree and checks that printing it gives back the file
+//! byte for byte, including the files with errors in them.
+
+mod common;
+
+use std::fs;
+
+use loxite::cst::SyntaxNode;
+
+#[test]
+fn test_cst_round_trips_corpus() {
+    let files = common::lox_files();
+
+    let mut failures = vec![];
+    for file in &files {
+        let source = fs::read_to_string(file).expect("Could not read the test file");
+        if SyntaxNode::parse(&source).to_string() != source {
+            failures.push(file.display().to_string());
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "not printed back identically:\n{}",
+        failures.join("\n")
+    );
+}